@@ -5,8 +5,9 @@ use proc_macro2::Span;
 use quote::quote;
 use syn::parse::{Parse, ParseStream};
 use syn::{
-    parenthesized, parse_macro_input, punctuated::Punctuated, FnArg, Generics, Ident, LitInt,
-    LitStr, Pat, PatIdent, PatType, Result, Token, Type, TypePath, Visibility,
+    bracketed, parse_macro_input, punctuated::Punctuated, token, Attribute, FnArg, GenericParam,
+    Ident, ItemFn, Lit, LitInt, LitStr, Meta, MetaNameValue, PatType, Path, Result, ReturnType,
+    Token, Type,
 };
 
 trait LitIntExt {
@@ -17,27 +18,19 @@ trait LitIntExt {
 
 impl LitIntExt for LitInt {}
 
-trait IdentExt {
-    fn prepend(&self, string: &str) -> Ident;
-}
-
-impl IdentExt for syn::Ident {
-    fn prepend(&self, string: &str) -> Ident {
-        Ident::new(&format!("{}{}", string, self), self.span())
-    }
-}
-
 #[derive(Debug)]
 struct Route {
     pub segments: Vec<Segment>,
     pub static_segment_positions: Vec<LitInt>,
     pub dynamic_segment_positions: Vec<LitInt>,
+    pub catch_all: Option<(CatchAllSegment, LitInt)>,
+    pub optional_positions: Vec<LitInt>,
 }
 
 #[derive(Debug)]
 enum Segment {
     Static(StaticSegment),
-    Dynamic(DynamicSegment),
+    Dynamic(Box<DynamicSegment>),
 }
 
 #[derive(Debug)]
@@ -48,42 +41,110 @@ struct StaticSegment {
 #[derive(Debug)]
 struct DynamicSegment {
     pub field_name: Ident,
+    pub ty: Option<Type>,
+    pub pattern: Option<LitStr>,
+}
+
+#[derive(Debug)]
+struct CatchAllSegment {
+    pub field_name: Ident,
 }
 
 impl Parse for Route {
     fn parse(input: ParseStream) -> Result<Self> {
         let _: Token![/] = input.parse()?;
 
-        let mut count = 0;
-        let mut static_segment_positions = vec![];
-        let mut dynamic_segment_positions = vec![];
-
-        let segments = {
-            let mut segments = vec![];
-            while !input.is_empty() {
-                let lookahead = input.lookahead1();
-                if lookahead.peek(LitStr) {
-                    segments.push(input.parse().map(Segment::Static)?);
-                    static_segment_positions.push(LitInt::from_usize(count));
-                    count += 1;
-                } else if lookahead.peek(Ident) {
-                    segments.push(input.parse().map(Segment::Dynamic)?);
-                    dynamic_segment_positions.push(LitInt::from_usize(count));
-                    count += 1;
-                } else if lookahead.peek(Token![/]) {
-                    let _: Token![/] = input.parse()?;
+        let mut route = Self {
+            segments: vec![],
+            static_segment_positions: vec![],
+            dynamic_segment_positions: vec![],
+            catch_all: None,
+            optional_positions: vec![],
+        };
+
+        route.parse_segments(input, false)?;
+
+        Ok(route)
+    }
+}
+
+impl Route {
+    /// Parse segments separated by `/` until the input is empty.  `group` is true while parsing the
+    /// contents of an optional group.
+    fn parse_segments(&mut self, input: ParseStream, group: bool) -> Result<()> {
+        while !input.is_empty() {
+            let count = self.segments.len() + self.catch_all.iter().count();
+            let optional_tail = !group && !self.optional_positions.is_empty();
+
+            let lookahead = input.lookahead1();
+            if lookahead.peek(Token![/]) {
+                let _: Token![/] = input.parse()?;
+            } else if lookahead.peek(token::Bracket) {
+                if group {
+                    return Err(input.error("optional groups cannot be nested"));
+                }
+
+                let content;
+                let brackets = bracketed!(content in input);
+
+                if content.is_empty() {
+                    return Err(syn::Error::new(
+                        brackets.span,
+                        "an optional group must contain at least one segment",
+                    ));
+                }
+
+                self.optional_positions.push(LitInt::from_usize(count));
+                self.parse_segments(&content, true)?;
+            } else if lookahead.peek(Ident) && input.peek2(Token![..]) {
+                if group || optional_tail {
+                    return Err(input.error("a catch-all segment cannot follow optional segments"));
+                }
+
+                self.catch_all = Some((input.parse()?, LitInt::from_usize(count)));
+
+                if !input.is_empty() {
+                    return Err(input.error("a catch-all segment must be the last segment"));
+                }
+            } else if lookahead.peek(LitStr) || lookahead.peek(Ident) {
+                let span = input.span();
+                let segment = if input.peek(LitStr) {
+                    self.static_segment_positions
+                        .push(LitInt::from_usize(count));
+                    input.parse().map(Segment::Static)?
                 } else {
-                    return Err(lookahead.error());
+                    self.dynamic_segment_positions
+                        .push(LitInt::from_usize(count));
+                    input
+                        .parse()
+                        .map(|segment| Segment::Dynamic(Box::new(segment)))?
+                };
+
+                if input.peek(Token![?]) {
+                    let question: Token![?] = input.parse()?;
+
+                    if group {
+                        return Err(syn::Error::new(
+                            question.span,
+                            "segments inside an optional group cannot be optional",
+                        ));
+                    }
+
+                    self.optional_positions.push(LitInt::from_usize(count));
+                } else if optional_tail {
+                    return Err(syn::Error::new(
+                        span,
+                        "only optional segments can follow an optional segment",
+                    ));
                 }
+
+                self.segments.push(segment);
+            } else {
+                return Err(lookahead.error());
             }
-            segments
-        };
+        }
 
-        Ok(Self {
-            segments,
-            static_segment_positions,
-            dynamic_segment_positions,
-        })
+        Ok(())
     }
 }
 
@@ -99,6 +160,36 @@ impl Parse for DynamicSegment {
     fn parse(input: ParseStream) -> Result<Self> {
         let field_name = input.parse()?;
 
+        let mut ty = None;
+        let mut pattern = None;
+
+        if input.peek(Token![:]) {
+            let _: Token![:] = input.parse()?;
+            ty = Some(input.parse()?);
+        } else if input.peek(Token![~]) {
+            let _: Token![~] = input.parse()?;
+            let lit: LitStr = input.parse()?;
+
+            if let Err(e) = regex::Regex::new(&format!("^(?:{})$", lit.value())) {
+                return Err(syn::Error::new(lit.span(), e));
+            }
+
+            pattern = Some(lit);
+        }
+
+        Ok(Self {
+            field_name,
+            ty,
+            pattern,
+        })
+    }
+}
+
+impl Parse for CatchAllSegment {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let field_name = input.parse()?;
+        let _: Token![..] = input.parse()?;
+
         Ok(Self { field_name })
     }
 }
@@ -137,11 +228,13 @@ impl Route {
 
     fn dynamic_segments(&self) -> proc_macro2::TokenStream {
         let mut dynamic_segment_names = vec![];
+        let mut dynamic_segment_constraints = vec![];
 
         self.segments.iter().for_each(|segment| match segment {
             Segment::Dynamic(dynamic_segment) => {
                 let name = &dynamic_segment.field_name.to_string();
                 dynamic_segment_names.push(quote!(#name));
+                dynamic_segment_constraints.push(dynamic_segment.constraint());
             }
             _ => {}
         });
@@ -153,6 +246,7 @@ impl Route {
                 dynamic_segments.push(DynamicSegment {
                     name: #dynamic_segment_names,
                     position: #dynamic_positions,
+                    constraint: #dynamic_segment_constraints,
                 });
             )*
         };
@@ -165,6 +259,61 @@ impl Route {
             }
         }
     }
+
+    fn expand(&self) -> proc_macro2::TokenStream {
+        let dynamic_segments = self.dynamic_segments();
+        let static_segments = self.static_segments();
+        let catch_all = self.catch_all();
+        let optional_positions = &self.optional_positions;
+
+        quote! {
+            Route {
+                static_segments: #static_segments,
+                dynamic_segments: #dynamic_segments,
+                catch_all: #catch_all,
+                optional_positions: vec![#(#optional_positions),*],
+                produces: None,
+                consumes: None,
+                handler: None,
+                middleware: Vec::new(),
+                name: None,
+                metadata: None,
+            }
+        }
+    }
+
+    fn catch_all(&self) -> proc_macro2::TokenStream {
+        match &self.catch_all {
+            Some((catch_all, position)) => {
+                let name = &catch_all.field_name.to_string();
+                quote! {
+                    Some(DynamicSegment {
+                        name: #name,
+                        position: #position,
+                        constraint: None,
+                    })
+                }
+            }
+            None => quote!(None),
+        }
+    }
+}
+
+impl DynamicSegment {
+    fn constraint(&self) -> proc_macro2::TokenStream {
+        match (&self.ty, &self.pattern) {
+            (Some(ty), _) => {
+                let name = type_name(ty);
+                quote! {
+                    Some(Constraint::Type(#name, |segment: &str| {
+                        <#ty as std::str::FromStr>::from_str(segment).is_ok()
+                    }))
+                }
+            }
+            (None, Some(pattern)) => quote!(Some(Constraint::Pattern(#pattern))),
+            (None, None) => quote!(None),
+        }
+    }
 }
 
 /// The `route!` macro is used to generate a [`Route`](struct.Route.html) from a path.  
@@ -178,138 +327,568 @@ impl Route {
 /// ## Paths
 ///
 /// The `route!` macro takes a list of string literals and idents beginning with and separated by `/`.  
+///
+/// ## Typed segments
+///
+/// A dynamic segment may be followed by `:` and a type implementing `FromStr`.  The route only
+/// matches when the segment parses as that type, otherwise the router falls through to the next
+/// matching route.  
+/// ```ignore
+/// route!(/"users"/id: u64)
+/// ```
+/// A request for `/users/42` matches the route above while `/users/abc` does not.  Typed segments
+/// are tried before untyped segments in the same position.  
+///
+/// ## Pattern segments
+///
+/// A dynamic segment may instead be followed by `~` and a regular expression.  The route only
+/// matches when the whole segment matches the expression.  The expression is validated when the
+/// macro is expanded and compiled once when the route is added to the router.  
+/// ```ignore
+/// route!(/"files"/name ~ "[a-z0-9_-]+\\.png")
+/// ```
+/// Like typed segments, pattern segments are tried before untyped segments in the same position,
+/// in the order they were added.  
+///
+/// ## Catch-all segments
+///
+/// The last segment of a route may be an ident followed by `..`, which captures the remaining path,
+/// slashes included.  The catch-all is written `path..` rather than `*path`, after the rest
+/// patterns of Rust slices.  The catch-all matches one or more segments and never captures an
+/// empty path.  
+/// ```ignore
+/// route!(/"static"/path..)
+/// ```
+/// A request for `/static/css/site.css` captures `css/site.css` as `path`, while neither `/static`
+/// nor `/static/` match the route.
+///
+/// ## Optional segments
+///
+/// A segment followed by `?`, or a group of segments in brackets, is optional.  Optional segments
+/// must come at the end of the route and cannot be combined with a catch-all segment.
+/// ```ignore
+/// route!(/"posts"/id?)
+/// route!(/"list"/["page"/page])
+/// ```
+/// The first route matches both `/posts` and `/posts/42`, the second matches both `/list` and
+/// `/list/page/2`.  Params for segments missing from the request are absent from `Params`.
 #[proc_macro]
 pub fn route(tokens: TokenStream) -> TokenStream {
     let input = parse_macro_input!(tokens as Route);
 
-    let dynamic_segments = input.dynamic_segments();
-    let static_segments = input.static_segments();
-
-    let output = quote! {
-        Route {
-            static_segments: #static_segments,
-            dynamic_segments: #dynamic_segments,
-            handler: None,
-        }
-    };
-
-    output.into()
+    input.expand().into()
 }
 
 #[derive(Debug)]
 struct Endpoint {
-    tokens: proc_macro2::TokenStream,
+    item: ItemFn,
+    props_types: Vec<Type>,
+}
+
+/// The method and route an endpoint is registered under, given as arguments to `#[endpoint]`.
+#[derive(Debug)]
+struct Registration {
+    method: proc_macro2::TokenStream,
+    route: Route,
+}
+
+impl Parse for Registration {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let method: Ident = input.parse()?;
+        let variant = match method.to_string().to_ascii_uppercase().as_str() {
+            "CONNECT" => "Connect",
+            "DELETE" => "Delete",
+            "GET" => "Get",
+            "HEAD" => "Head",
+            "OPTIONS" => "Options",
+            "PATCH" => "Patch",
+            "POST" => "Post",
+            "PUT" => "Put",
+            "TRACE" => "Trace",
+            _ => return Err(syn::Error::new(method.span(), "unknown HTTP method")),
+        };
+        let variant = Ident::new(variant, method.span());
+
+        let _: Token![,] = input.parse()?;
+        let route = input.parse()?;
+
+        Ok(Self {
+            method: quote!(http_types::Method::#variant),
+            route,
+        })
+    }
 }
 
 impl Parse for Endpoint {
     fn parse(input: ParseStream) -> Result<Self> {
-        let _visibility: Visibility = input.parse()?;
-        let _async: Option<Token![async]> = input.parse()?;
-        let _fn: Token![fn] = input.parse()?;
-        let fn_name: Ident = input.parse()?;
-        let _generics: Generics = input.parse()?;
-        let content;
-        let _paren = parenthesized!(content in input);
-        let args: Punctuated<FnArg, Token![,]> = content.parse_terminated(FnArg::parse)?;
-
-        let hidden_fn_name = fn_name.prepend("___");
-
-        let mut fn_args = vec![];
-        let mut props_calls = vec![];
-
-        for arg in args {
-            if let FnArg::Typed(PatType { pat, ty, .. }) = arg {
-                if let Type::Path(TypePath { path, .. }) = *ty {
-                    if let Pat::Ident(PatIdent { ident, .. }) = *pat {
-                        let ident = quote!(#ident);
-                        let ty = quote!(#path);
-
-                        let props_call = quote! {
-                            let (req, params, #ident) =
-                                <#ty as Props>::call(req, params).await?;
-                        };
-
-                        props_calls.push(props_call);
-                        fn_args.push(ident);
-                    }
+        let item: ItemFn = input.parse()?;
+        let sig = &item.sig;
+
+        let mut errors = vec![];
+
+        if sig.asyncness.is_none() {
+            errors.push(syn::Error::new(
+                sig.ident.span(),
+                "endpoints must be `async` functions",
+            ));
+        }
+
+        if let ReturnType::Default = sig.output {
+            errors.push(syn::Error::new(
+                sig.ident.span(),
+                "endpoints must return a value implementing `IntoResponse`",
+            ));
+        }
+
+        if let Some(lifetime) = sig.generics.lifetimes().next() {
+            errors.push(syn::Error::new(
+                lifetime.lifetime.span(),
+                "an endpoint cannot have lifetime parameters",
+            ));
+        }
+
+        let mut props_types = vec![];
+
+        for arg in &sig.inputs {
+            let ty = match arg {
+                FnArg::Receiver(receiver) => {
+                    errors.push(syn::Error::new_spanned(
+                        receiver,
+                        "endpoints cannot take `self`, every argument must be a props",
+                    ));
+                    continue;
                 }
+                FnArg::Typed(PatType { ty, .. }) => ty,
+            };
+
+            match check_props_type(ty) {
+                Ok(()) => props_types.push((**ty).clone()),
+                Err(e) => errors.push(e),
             }
         }
 
-        let generated_props_calls = quote!(#(#props_calls)*);
+        let mut errors = errors.into_iter();
+        if let Some(mut error) = errors.next() {
+            errors.for_each(|e| error.combine(e));
+            return Err(error);
+        }
+
+        Ok(Self { item, props_types })
+    }
+}
 
-        let generated_endpoint_call = quote! {
-            Ok(#fn_name(#(#fn_args),*).await?)
-        };
+impl Endpoint {
+    fn expand(&self, registration: Option<&Registration>) -> proc_macro2::TokenStream {
+        let ItemFn {
+            attrs,
+            vis,
+            sig,
+            block,
+        } = &self.item;
+        let name = &sig.ident;
+        let inputs = &sig.inputs;
+        let output = &sig.output;
+
+        let (doc_attrs, attrs): (Vec<&Attribute>, Vec<&Attribute>) =
+            attrs.iter().partition(|attr| attr.path.is_ident("doc"));
+
+        let generics = &sig.generics;
+        let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+        let endpoint_struct = match generics.params.is_empty() {
+            true => quote! {
+                #(#doc_attrs)*
+                #[allow(non_camel_case_types)]
+                #[derive(Clone, Copy, Default)]
+                #vis struct #name {}
+            },
+            false => {
+                let mut struct_params = vec![];
+                let mut type_params = vec![];
+
+                for param in &generics.params {
+                    match param {
+                        GenericParam::Type(param) => {
+                            let ident = &param.ident;
+                            struct_params.push(quote!(#ident));
+                            type_params.push(ident);
+                        }
+                        GenericParam::Const(param) => {
+                            let ident = &param.ident;
+                            let ty = &param.ty;
+                            struct_params.push(quote!(const #ident: #ty));
+                        }
+                        GenericParam::Lifetime(_) => {}
+                    }
+                }
+
+                quote! {
+                    #(#doc_attrs)*
+                    #[allow(non_camel_case_types)]
+                    #vis struct #name<#(#struct_params),*> {
+                        _marker: std::marker::PhantomData<fn() -> (#(#type_params,)*)>,
+                    }
+
+                    impl<#(#struct_params),*> Clone for #name #ty_generics {
+                        fn clone(&self) -> Self {
+                            *self
+                        }
+                    }
+
+                    impl<#(#struct_params),*> Copy for #name #ty_generics {}
 
-        let endpoint_fn = quote! {
-            async fn #hidden_fn_name(
-                req: http_types::Request,
-                params: Params
-            ) -> Result<http_types::Response, Error> {
-                #generated_props_calls
-                #generated_endpoint_call
+                    impl<#(#struct_params),*> Default for #name #ty_generics {
+                        fn default() -> Self {
+                            Self {
+                                _marker: std::marker::PhantomData,
+                            }
+                        }
+                    }
+                }
             }
         };
 
-        parse_to_end(input);
+        let register_fn = registration.map(|registration| {
+            let method = &registration.method;
+            let route = registration.route.expand();
 
-        Ok(Self {
-            tokens: endpoint_fn,
+            quote! {
+                /// Add the endpoint to the router under the method and route it was declared with.
+                #vis fn register(router: &mut Router) {
+                    router.add(#method, #route, Self::default());
+                }
+            }
+        });
+
+        let args: Vec<Ident> = (0..self.props_types.len())
+            .map(|index| Ident::new(&format!("__arg{}", index), Span::call_site()))
+            .collect();
+        let props_types = &self.props_types;
+
+        let name_str = name.to_string();
+        let doc = doc(&doc_attrs);
+        let arg_strs = props_types.iter().map(type_name);
+
+        quote! {
+            #endpoint_struct
+
+            #(#doc_attrs)*
+            #vis fn #name #impl_generics() -> #name #ty_generics #where_clause {
+                Default::default()
+            }
+
+            impl #impl_generics #name #ty_generics #where_clause {
+                #(#attrs)*
+                #vis async fn handler(#inputs) #output #block
+
+                #register_fn
+            }
+
+            impl #impl_generics Endpoint for #name #ty_generics #where_clause {
+                type Fut = std::pin::Pin<
+                    Box<
+                        dyn std::future::Future<Output = Result<http_types::Response, Error>>
+                            + Send
+                            + Sync,
+                    >,
+                >;
+
+                fn call(&self, req: http_types::Request, params: Params) -> Self::Fut {
+                    Box::pin(async move {
+                        #(
+                            let (req, params, #args) =
+                                <#props_types as Props>::call(req, params).await?;
+                        )*
+                        Ok(IntoResponse::into_response(Self::handler(#(#args),*).await))
+                    })
+                }
+
+                fn metadata(&self) -> Metadata {
+                    Metadata {
+                        name: #name_str,
+                        doc: #doc,
+                        args: &[#(#arg_strs),*],
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The text of the doc comments, with the space following each `///` removed.
+fn doc(attrs: &[&Attribute]) -> String {
+    attrs
+        .iter()
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(Meta::NameValue(MetaNameValue {
+                lit: Lit::Str(lit), ..
+            })) => Some(lit.value()),
+            _ => None,
+        })
+        .map(|line| match line.strip_prefix(' ') {
+            Some(line) => line.to_string(),
+            None => line,
         })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The type as written, like `Body<String>`, rather than spaced out between every token.
+fn type_name(ty: &Type) -> String {
+    let tokens = quote!(#ty).to_string();
+    let mut name = String::new();
+    let mut chars = tokens.chars().peekable();
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '\'';
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            (' ', Some(&next)) if is_word(next) && name.ends_with(is_word) => name.push(' '),
+            (' ', _) => {}
+            (',', _) => name.push_str(", "),
+            _ => name.push(c),
+        }
     }
+
+    name
 }
 
-fn parse_to_end(input: ParseStream) {
-    while !input.is_empty() {
-        let _ = input.step(|cursor| {
-            let mut rest = *cursor;
-            while let Some((_, next)) = rest.token_tree() {
-                rest = next;
-            }
-            Ok(((), rest))
-        });
+/// Props are constructed from the request and passed by value, so an argument must be a path to a
+/// type, like `Body<T>`.
+fn check_props_type(ty: &Type) -> Result<()> {
+    match ty {
+        Type::Path(_) => Ok(()),
+        Type::Group(group) => check_props_type(&group.elem),
+        Type::Paren(paren) => check_props_type(&paren.elem),
+        Type::Reference(_) => Err(syn::Error::new_spanned(
+            ty,
+            "props are passed by value, reference arguments are not supported",
+        )),
+        Type::ImplTrait(_) => Err(syn::Error::new_spanned(
+            ty,
+            "`impl Trait` arguments are not supported, use a generic parameter instead",
+        )),
+        _ => Err(syn::Error::new_spanned(
+            ty,
+            "endpoint arguments must be props types, like `Body<T>`",
+        )),
     }
 }
-/// # The macro used to generate the hidden endpoint functions.  
+
+/// # The macro used to turn async functions into endpoints.  
+///
+/// The `#[endpoint]` macro replaces the function with a type of the same name implementing
+/// `Endpoint`, along with a function of the same name returning it, which is passed to the router
+/// in place of the original function.  The endpoint constructs the argument props in a
+/// short-circut fashion from left to right.  Finally it invokes the function, passing in the
+/// props, and converts the value it returns into a response with `IntoResponse`.  
+///
+/// The function itself is moved to the `handler` associated function of the type.  The name, the
+/// doc comments and the props types of the endpoint are available through `Endpoint::metadata`.  
+///
+/// The type is a struct with braces, so it does not take the name of the endpoint as a value.  The
+/// generated function does, like the original function did, and `let` bindings may shadow it.  
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// #[endpoint(GET, /"users")]
+/// async fn users() -> &'static str {
+///     "users"
+/// }
+///
+/// let users = ["bob", "alice"];
+/// assert_eq!(users.len(), 2);
+///
+/// let mut router = Router::new();
+/// router.register(routes![users]);
+///
+/// let route = router.routes().next().unwrap();
+/// assert_eq!(route.metadata().unwrap().name, "users");
+/// ```
 ///
-/// The `#[endpoint]` macro generates a function that constructs the argument props for an endpoint in
-/// a short-circut fashion from left to right.  Finally the function invokes the endpoint, passing in
-/// the props.  The name of the function is the name of then endpoint preceeded by `___`.  
-/// 
 /// # Examples
 /// ```ignore
+/// /// Handles everything.
 /// #[endpoint]
 /// async fn my_main_handler(env: EnvVarsProps, body: Body<String>) -> Result<http_types::Response, Error> {
 ///     let response = http_types::Response::new(http_types::StatusCode::Ok);
 ///     Ok(response)
 /// }
+///
+/// router.add(Method::Get, route!(/"main"), my_main_handler);
 /// ```
 /// Generates the following code
 ///
 /// ```ignore
-/// async fn ___my_main_handler(req: http_types::Request, params: Params) -> Result<http_types::Response, Error> {
-///     let (req, params, env) = <EnvVarsProps as Props>::call(req, params).await?;
-///     let (req, params, body) = <Body<String> as Props>::call(req, params).await?;
-///     Ok(my_main_handler(env, body).await?)
+/// /// Handles everything.
+/// #[derive(Clone, Copy, Default)]
+/// struct my_main_handler {}
+///
+/// /// Handles everything.
+/// fn my_main_handler() -> my_main_handler {
+///     Default::default()
 /// }
-/// async fn my_main_handler(env: EnvVarsProps, body: Body<String>) -> Result<http_types::Response, Error> {
-///     let response = http_types::Response::new(http_types::StatusCode::Ok);
-///     Ok(response)
+///
+/// impl my_main_handler {
+///     async fn handler(env: EnvVarsProps, body: Body<String>) -> Result<http_types::Response, Error> {
+///         let response = http_types::Response::new(http_types::StatusCode::Ok);
+///         Ok(response)
+///     }
+/// }
+///
+/// impl Endpoint for my_main_handler {
+///     type Fut = Pin<Box<dyn Future<Output = Result<http_types::Response, Error>> + Send + Sync>>;
+///
+///     fn call(&self, req: http_types::Request, params: Params) -> Self::Fut {
+///         Box::pin(async move {
+///             let (req, params, __arg0) = <EnvVarsProps as Props>::call(req, params).await?;
+///             let (req, params, __arg1) = <Body<String> as Props>::call(req, params).await?;
+///             Ok(IntoResponse::into_response(Self::handler(__arg0, __arg1).await))
+///         })
+///     }
+///
+///     fn metadata(&self) -> Metadata {
+///         Metadata {
+///             name: "my_main_handler",
+///             doc: "Handles everything.",
+///             args: &["EnvVarsProps", "Body<String>"],
+///         }
+///     }
+/// }
+/// ```
+///
+/// # Arguments
+///
+/// Every argument of an endpoint must be a props, named by a path to its type.  Arguments may use
+/// any pattern, like `mut body: Body<T>` or `Pair(a, b): Pair`, since the handler binds them
+/// itself.  The macro reports an error at the argument for `self`, references, `impl Trait` and
+/// any other type, and at the name of the endpoint when it is not `async` or returns nothing.  
+/// ```compile_fail
+/// # use windmill::*;
+/// struct Users;
+///
+/// impl Users {
+///     #[endpoint]
+///     async fn list(self) -> &'static str {
+///         "users"
+///     }
+/// }
+/// ```
+/// ```compile_fail
+/// # use windmill::*;
+/// # use std::collections::HashMap;
+/// #[endpoint]
+/// async fn search(query: &Query<HashMap<String, String>>) -> String {
+///     format!("{:?}", query.inner)
+/// }
+/// ```
+/// ```compile_fail
+/// # use windmill::*;
+/// #[endpoint]
+/// async fn search(query: impl Props) -> &'static str {
+///     "results"
+/// }
+/// ```
+/// ```compile_fail
+/// # use windmill::*;
+/// #[endpoint]
+/// fn hello() -> &'static str {
+///     "Hello!"
+/// }
+/// ```
+/// ```compile_fail
+/// # use windmill::*;
+/// #[endpoint]
+/// async fn hello() {}
+/// ```
+///
+/// # Generics
+///
+/// The type and const parameters of a generic endpoint become parameters of its type and of the
+/// generated function, and its where clause applies to the `Endpoint` implementation.  Lifetime
+/// parameters are rejected, the type of an endpoint has to be `'static`.  
+/// ```ignore
+/// #[endpoint]
+/// async fn list<R>(repo: State<R>) -> Json<Vec<Item>>
+/// where
+///     R: Repo + Send + Sync + 'static,
+/// {
+///     Json(repo.inner.list().await)
+/// }
+///
+/// router.add(Method::Get, route!(/"items"), list::<PgRepo>);
+/// ```
+///
+/// # Registration
+///
+/// The macro optionally takes a method and a route, using the syntax of the
+/// [`route!`](macro.route.html) macro.  
+/// ```ignore
+/// #[endpoint(GET, /"hello"/name)]
+/// async fn hello() -> Result<http_types::Response, Error> {
+///     Ok(http_types::Response::new(http_types::StatusCode::Ok))
 /// }
+/// ```
+/// Additionally generates an associated function adding the endpoint to a router, which
+/// [`routes!`](macro.routes.html) refers to.  
 ///
+/// ```ignore
+/// impl hello {
+///     fn register(router: &mut Router) {
+///         router.add(http_types::Method::Get, route!(/"hello"/name), Self::default());
+///     }
+/// }
 /// ```
 #[proc_macro_attribute]
-pub fn endpoint(_attrs: TokenStream, tokens: TokenStream) -> TokenStream {
+pub fn endpoint(attrs: TokenStream, tokens: TokenStream) -> TokenStream {
+    let registration = match attrs.is_empty() {
+        true => Ok(None),
+        false => syn::parse::<Registration>(attrs).map(Some),
+    };
+
     let tokens_clone = tokens.clone();
-    let input = parse_macro_input!(tokens_clone as Endpoint);
 
-    let endpoint_fn = input.tokens;
-    let tokens2: proc_macro2::TokenStream = tokens.into();
+    // The function is kept when it cannot be turned into an endpoint or its arguments are invalid,
+    // so the errors are reported at their cause rather than where the function is used.
+    let (registration, input) = match (registration, syn::parse::<Endpoint>(tokens_clone)) {
+        (Ok(registration), Ok(input)) => (registration, input),
+        (registration, input) => {
+            let errors = registration
+                .err()
+                .into_iter()
+                .chain(input.err())
+                .map(|e| e.to_compile_error());
+            let tokens: proc_macro2::TokenStream = tokens.into();
+            return quote!(#(#errors)* #tokens).into();
+        }
+    };
+
+    input.expand(registration.as_ref()).into()
+}
+
+/// The `routes!` macro collects endpoints registered with a method and a route through the
+/// [`#[endpoint]`](attr.endpoint.html) macro, for
+/// [`Router::register`](struct.Router.html#method.register).  
+/// ```ignore
+/// router.register(routes![hello, users::show, list::<PgRepo>]);
+/// ```
+/// Expands to a closure adding every endpoint to the router it is given.  
+///
+/// ```ignore
+/// |router: &mut Router| {
+///     hello::register(router);
+///     users::show::register(router);
+///     list::<PgRepo>::register(router);
+/// }
+/// ```
+#[proc_macro]
+pub fn routes(tokens: TokenStream) -> TokenStream {
+    let paths = parse_macro_input!(tokens with Punctuated::<Path, Token![,]>::parse_terminated);
+    let paths = paths.into_iter();
 
     let output = quote! {
-        #endpoint_fn
-        #tokens2
+        |router: &mut Router| {
+            #(#paths::register(router);)*
+        }
     };
 
     output.into()
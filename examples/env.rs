@@ -20,7 +20,7 @@ fn main() {
     let config = Config::new("127.0.0.1:4000");
 
     #[rustfmt::skip]
-        router.add(Method::Get, route!(/"main"), ___my_main_handler);
+        router.add(Method::Get, route!(/"main"), my_main_handler);
 
     if let Err(e) = Server::new(config).run(router) {
         println!("{}", e);
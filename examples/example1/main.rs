@@ -16,8 +16,11 @@ fn main() {
     let config = Config::new("127.0.0.1:4000");
 
     #[rustfmt::skip]
-    router.add(Method::Get, route!(/"example"/id), ___example_route);
-    router.add(Method::Get, route!(/"hello"/name), ___hello);
+    router.group(route!(/"example"), |example| {
+        example.guard::<Auth>();
+        example.add(Method::Get, route!(/id: u64), example_route);
+    });
+    router.add(Method::Get, route!(/"hello"/name), hello);
 
     if let Err(e) = Server::new(config).run(router) {
         println!("{}", e);
@@ -25,11 +28,7 @@ fn main() {
 }
 
 #[endpoint]
-async fn example_route(
-    _auth: Auth,
-    id: Id,
-    body: Body<ExampleRequest>,
-) -> Result<http_types::Response, Error> {
+async fn example_route(id: Id, body: Body<ExampleRequest>) -> Result<http_types::Response, Error> {
     let body = body.inner.ok_or_else(|| Error {
         code: StatusCode::BadRequest,
         msg: serde_json::json!("body required"),
@@ -1,22 +1,69 @@
 use crate::{error::Error, Params};
-use std::{future::Future, pin::Pin};
+use std::future::Future;
 
 /// A trait for things that can be used as routes.  
 pub trait Endpoint: 'static + Copy {
     type Fut: Future<Output = Result<http_types::Response, Error>> + Send + Sync + 'static;
     fn call(&self, req: http_types::Request, params: Params) -> Self::Fut;
+
+    /// Metadata describing the endpoint, by default only its type name.  
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            name: std::any::type_name::<Self>(),
+            doc: "",
+            args: &[],
+        }
+    }
+}
+
+/// Metadata describing an endpoint.  
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use http_types::Method;
+/// # use std::collections::HashMap;
+/// /// Show a user.
+/// #[endpoint]
+/// async fn show_user(query: Query<HashMap<String, String>>) -> &'static str {
+///     "bob"
+/// }
+///
+/// let mut router = Router::new();
+/// router.add(Method::Get, route!(/"users"/id), show_user);
+///
+/// let route = router.routes().next().unwrap();
+/// let metadata = route.metadata().unwrap();
+///
+/// assert_eq!(metadata.name, "show_user");
+/// assert_eq!(metadata.doc, "Show a user.");
+/// assert_eq!(metadata.args, ["Query<HashMap<String, String>>"]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Metadata {
+    /// The name of the endpoint.  
+    pub name: &'static str,
+    /// The doc comments of the endpoint.  
+    pub doc: &'static str,
+    /// The props types the endpoint takes, in order.  
+    pub args: &'static [&'static str],
 }
 
-/// A blanket impl over the generated hidden functions for endpoints.  
-impl<F, G> Endpoint for F
+/// A blanket impl over the functions generated by [`#[endpoint]`](attr.endpoint.html), which
+/// return the endpoint type of the same name.  
+impl<F, E> Endpoint for F
 where
-    F: Fn(http_types::Request, Params) -> G + Copy + 'static,
-    G: Future<Output = Result<http_types::Response, Error>> + Send + Sync + 'static,
+    F: Fn() -> E + Copy + 'static,
+    E: Endpoint,
 {
-    type Fut = Pin<Box<dyn Future<Output = Result<http_types::Response, Error>> + Send + Sync>>;
+    type Fut = E::Fut;
 
     fn call(&self, req: http_types::Request, params: Params) -> Self::Fut {
-        let fut = (self)(req, params);
-        Box::pin(async move { fut.await })
+        (self)().call(req, params)
+    }
+
+    fn metadata(&self) -> Metadata {
+        (self)().metadata()
     }
 }
@@ -1,3 +1,6 @@
+use http_types::Method;
+use std::fmt;
+
 /// A HTTP error.  
 #[derive(Debug)]
 pub struct Error {
@@ -16,3 +19,38 @@ impl Error {
         &self.msg
     }
 }
+
+/// An error returned when a route cannot be added to a router.  
+#[derive(Debug)]
+pub enum RouteError {
+    /// A route with the same method and the same shape already exists, so the new route could
+    /// never match.  
+    Duplicate {
+        method: Method,
+        route: String,
+        existing: String,
+    },
+    /// A route with the same name already exists.  
+    DuplicateName(&'static str),
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RouteError::Duplicate {
+                method,
+                route,
+                existing,
+            } => write!(
+                f,
+                "{} {} can never match, it conflicts with {} {}",
+                method, route, method, existing
+            ),
+            RouteError::DuplicateName(name) => {
+                write!(f, "a route named `{}` already exists", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RouteError {}
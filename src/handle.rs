@@ -0,0 +1,100 @@
+use crate::router::Router;
+use std::sync::{Arc, RwLock};
+
+/// A handle to the router of a running server, used to swap in a new router without restarting
+/// the server.
+///
+/// Every request is routed by the router that was current when the request arrived, so requests
+/// in flight during a swap finish on the old router while new requests use the new one.  Handles
+/// are cheap to clone and every clone refers to the same router.
+///
+/// ## Examples
+/// ```no_run
+/// # use windmill::{Config, Router, RouterHandle, Server};
+/// let handle = RouterHandle::new(Router::new());
+/// let server_handle = handle.clone();
+///
+/// std::thread::spawn(move || {
+///     let config = Config::new("127.0.0.1:4000");
+///     if let Err(e) = Server::new(config).run(server_handle) {
+///         println!("{}", e);
+///     }
+/// });
+///
+/// // Later, once the new routes are ready.
+/// handle.swap(Router::new());
+/// ```
+#[derive(Clone)]
+pub struct RouterHandle {
+    inner: Arc<RwLock<Arc<Router>>>,
+}
+
+impl RouterHandle {
+    /// Create a new `RouterHandle` for the router.
+    pub fn new(router: Router) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(Arc::new(router))),
+        }
+    }
+
+    /// Replace the router with `router` for every request that arrives from now on.
+    pub fn swap(&self, router: Router) {
+        let router = Arc::new(router);
+        *self.inner.write().unwrap() = router;
+    }
+
+    /// The router requests are currently routed by.
+    pub(crate) fn current(&self) -> Arc<Router> {
+        self.inner.read().unwrap().clone()
+    }
+}
+
+impl From<Router> for RouterHandle {
+    fn from(router: Router) -> Self {
+        Self::new(router)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use http_types::{Method, Request, Url};
+    use std::sync::Arc;
+
+    #[endpoint]
+    async fn v1() -> &'static str {
+        "v1"
+    }
+
+    #[endpoint]
+    async fn v2() -> &'static str {
+        "v2"
+    }
+
+    fn version(router: Arc<Router>) -> String {
+        let url = Url::parse("http://localhost/version").unwrap();
+        let req = Request::new(Method::Get, url);
+
+        async_std::task::block_on(async {
+            let mut res = router.lookup(req).await.await;
+            res.body_string().await.unwrap()
+        })
+    }
+
+    #[test]
+    fn swapping_keeps_the_current_router_of_requests_in_flight() {
+        let mut router = Router::new();
+        router.add(Method::Get, route!(/"version"), v1);
+        let handle = RouterHandle::new(router);
+
+        let in_flight = handle.current();
+
+        let mut router = Router::new();
+        router.add(Method::Get, route!(/"version"), v2);
+        handle.swap(router);
+
+        assert_eq!(version(in_flight), "v1");
+        assert_eq!(version(handle.current()), "v2");
+        assert_eq!(version(handle.clone().current()), "v2");
+    }
+}
@@ -0,0 +1,127 @@
+use crate::params::Params;
+use http_types::headers::HeaderName;
+use std::str::FromStr;
+
+/// A pattern matching the host of a request label by label, like `{tenant}.example.com`.
+pub(crate) struct HostPattern {
+    pattern: &'static str,
+    labels: Vec<Label>,
+}
+
+enum Label {
+    Literal(String),
+    Capture(&'static str),
+}
+
+impl HostPattern {
+    /// Parse a host pattern.  Panics if a label is empty or has unbalanced braces.
+    pub(crate) fn new(pattern: &'static str) -> Self {
+        let labels = pattern
+            .trim_end_matches('.')
+            .split('.')
+            .map(|label| {
+                if label.len() > 2 && label.starts_with('{') && label.ends_with('}') {
+                    Label::Capture(&label[1..label.len() - 1])
+                } else if label.is_empty() || label.contains(['{', '}']) {
+                    panic!("invalid host pattern {:?}", pattern)
+                } else {
+                    Label::Literal(label.to_ascii_lowercase())
+                }
+            })
+            .collect();
+
+        Self { pattern, labels }
+    }
+
+    /// The pattern as it was written.
+    pub(crate) fn as_str(&self) -> &'static str {
+        self.pattern
+    }
+
+    /// Match a lowercased host against the pattern, capturing the labels in braces.
+    pub(crate) fn matches(&self, host: &str) -> Option<Params> {
+        let labels: Vec<&str> = host.split('.').collect();
+
+        if labels.len() != self.labels.len() {
+            return None;
+        }
+
+        let mut params = Params::new();
+
+        for (label, value) in self.labels.iter().zip(labels) {
+            match label {
+                Label::Literal(literal) if literal == value => {}
+                Label::Capture(name) if !value.is_empty() => {
+                    params.insert(name, value.to_string());
+                }
+                _ => return None,
+            }
+        }
+
+        Some(params)
+    }
+}
+
+/// The host a request was sent to, taken from the `Host` header or else from the URL, lowercased
+/// and without its port.
+pub(crate) fn request_host(req: &http_types::Request) -> Option<String> {
+    let header = req
+        .header(&HeaderName::from_str("host").unwrap())
+        .and_then(|values| values.first())
+        .map(|value| value.as_str());
+
+    let host = header.or_else(|| req.url().host_str())?;
+
+    let host = match host.find(']') {
+        Some(end) if host.starts_with('[') => &host[..=end],
+        _ => host.split(':').next().unwrap_or(host),
+    };
+
+    Some(host.trim_end_matches('.').to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{request_host, HostPattern};
+    use http_types::{headers::HeaderName, Method, Request, Url};
+    use std::str::FromStr;
+
+    fn host(url: &str, header: Option<&str>) -> Option<String> {
+        let mut req = Request::new(Method::Get, Url::parse(url).unwrap());
+
+        if let Some(header) = header {
+            let _ = req.insert_header(HeaderName::from_str("host").unwrap(), header);
+        }
+
+        request_host(&req)
+    }
+
+    #[test]
+    fn patterns_match_label_by_label() {
+        let pattern = HostPattern::new("{tenant}.Example.com");
+
+        let params = pattern.matches("acme.example.com").unwrap();
+        assert_eq!(params["tenant"], "acme");
+
+        assert!(pattern.matches("example.com").is_none());
+        assert!(pattern.matches("a.b.example.com").is_none());
+        assert!(pattern.matches("acme.example.org").is_none());
+        assert!(pattern.matches(".example.com").is_none());
+    }
+
+    #[test]
+    fn hosts_are_lowercased_without_their_port() {
+        let url = "http://localhost/";
+
+        assert_eq!(
+            host(url, Some("API.Example.com:8080")).unwrap(),
+            "api.example.com"
+        );
+        assert_eq!(host(url, Some("example.com.")).unwrap(), "example.com");
+        assert_eq!(host(url, Some("[::1]:8080")).unwrap(), "[::1]");
+        assert_eq!(
+            host("http://Example.com:4000/", None).unwrap(),
+            "example.com"
+        );
+    }
+}
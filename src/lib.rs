@@ -18,6 +18,18 @@
 //!     Ok(Response::from("Hello!"))
 //! }
 //! ```
+//! The `#[endpoint]` macro turns the function into an [`Endpoint`](trait.Endpoint.html), which is
+//! added to a router by the name of the function.
+//!
+//! An endpoint can return any type implementing [`IntoResponse`](trait.IntoResponse.html), like a
+//! string, a status code or a [`Json`](struct.Json.html) value:
+//! ```
+//! # pub use windmill::*;
+//! #[endpoint]
+//! async fn example_route() -> &'static str {
+//!     "Hello!"
+//! }
+//! ```
 //! ## Props
 //! Props are asynchronously constructed components that are passed into endpoints as function
 //! arguments.  
@@ -92,7 +104,7 @@
 //!     let mut router = Router::new();
 //!     let config = Config::new("127.0.0.1:4000");
 //!
-//!     router.add(Method::Get, route!(/"example"), ___example_route);
+//!     router.add(Method::Get, route!(/"example"), example_route);
 //!
 //!     if let Err(e) = Server::new(config).run(router) {
 //!         println!("{}", e);
@@ -103,15 +115,23 @@
 mod config;
 mod endpoint;
 mod error;
+mod handle;
+mod host;
+mod middleware;
+mod negotiation;
 mod props;
+mod query;
+mod response;
 mod route;
 mod router;
 mod server;
+mod tree;
 mod util;
 
 mod codegen {
     pub use codegen::endpoint;
     pub use codegen::route;
+    pub use codegen::routes;
 }
 
 mod params {
@@ -119,14 +139,18 @@ mod params {
 }
 
 pub use crate::{
-    codegen::{endpoint, route},
+    codegen::{endpoint, route, routes},
     config::Config,
-    endpoint::Endpoint,
-    error::Error,
+    endpoint::{Endpoint, Metadata},
+    error::{Error, RouteError},
+    handle::RouterHandle,
+    middleware::{Middleware, Next},
     params::Params,
     props::{Props, PropsFuture},
-    route::{DynamicSegment, Route, StaticSegment},
-    router::Router,
+    query::Query,
+    response::{IntoResponse, Json},
+    route::{Constraint, DynamicSegment, ResponseFuture, Route, StaticSegment},
+    router::{PathPolicy, RouteInfo, Router},
     server::Server,
-    util::read_body,
+    util::{normalize_path, read_body},
 };
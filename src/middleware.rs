@@ -0,0 +1,93 @@
+use crate::{
+    params::Params,
+    props::Props,
+    response::IntoResponse,
+    route::{ResponseFuture, RouteFn},
+};
+use std::{marker::PhantomData, sync::Arc};
+
+/// A trait for things that wrap endpoints.  
+///
+/// Middleware is handed the request, the params for the route and the rest of the pipeline as
+/// [`Next`](struct.Next.html).  It can inspect or modify the request before passing it on with
+/// `next.run`, short-circuit by returning a response without running `next` at all, or
+/// post-process the response that `next.run` yields.  
+///
+/// ## Examples
+/// ```
+/// # use windmill::*;
+/// struct Logger;
+///
+/// impl Middleware for Logger {
+///     fn call(&self, req: http_types::Request, params: Params, next: Next) -> ResponseFuture {
+///         Box::pin(async move {
+///             let path = req.url().path().to_string();
+///             let res = next.run(req, params).await;
+///             println!("{} {}", path, res.status());
+///             res
+///         })
+///     }
+/// }
+/// ```
+pub trait Middleware: Send + Sync + 'static {
+    fn call(&self, req: http_types::Request, params: Params, next: Next) -> ResponseFuture;
+}
+
+/// A blanket impl over functions that can be used as middleware.  
+impl<F> Middleware for F
+where
+    F: Fn(http_types::Request, Params, Next) -> ResponseFuture + Send + Sync + 'static,
+{
+    fn call(&self, req: http_types::Request, params: Params, next: Next) -> ResponseFuture {
+        (self)(req, params, next)
+    }
+}
+
+/// The rest of the middleware pipeline, ending with the endpoint.  
+pub struct Next {
+    middleware: Vec<Arc<dyn Middleware>>,
+    endpoint: RouteFn,
+}
+
+impl Next {
+    pub(crate) fn new(middleware: Vec<Arc<dyn Middleware>>, endpoint: RouteFn) -> Self {
+        Self {
+            middleware,
+            endpoint,
+        }
+    }
+
+    /// Run the rest of the pipeline.  
+    pub fn run(mut self, req: http_types::Request, params: Params) -> ResponseFuture {
+        if self.middleware.is_empty() {
+            (self.endpoint)(req, params)
+        } else {
+            let middleware = self.middleware.remove(0);
+            middleware.call(req, params, self)
+        }
+    }
+}
+
+/// Middleware that constructs the props `P` before running the rest of the pipeline.
+pub(crate) struct Guard<P> {
+    props: PhantomData<fn() -> P>,
+}
+
+impl<P> Guard<P> {
+    pub(crate) fn new() -> Self {
+        Self { props: PhantomData }
+    }
+}
+
+impl<P: Props + 'static> Middleware for Guard<P> {
+    fn call(&self, req: http_types::Request, params: Params, next: Next) -> ResponseFuture {
+        Box::pin(async move {
+            let (req, params) = match P::call(req, params).await {
+                Ok((req, params, _props)) => (req, params),
+                Err(e) => return e.into_response(),
+            };
+
+            next.run(req, params).await
+        })
+    }
+}
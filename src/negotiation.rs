@@ -0,0 +1,141 @@
+use crate::route::Route;
+use http_types::{headers::HeaderName, Mime, StatusCode};
+use std::str::FromStr;
+
+/// Pick the route for a request among routes with the same method and path that differ by the
+/// media types they consume and produce.
+///
+/// Routes are first narrowed down by the `Content-Type` of the request, failing with
+/// `415 Unsupported Media Type` when none consumes it.  The route producing the media type the
+/// `Accept` header prefers is then picked, a route that declares no media type is only picked
+/// when no other route is acceptable, and `406 Not Acceptable` is returned when no route is.
+/// Ties go to the route that was added first.
+pub(crate) fn negotiate<'r>(
+    routes: &[&'r Route],
+    req: &http_types::Request,
+) -> Result<&'r Route, StatusCode> {
+    let content_type = header(req, "content-type").and_then(media_type);
+
+    let routes: Vec<&Route> = routes
+        .iter()
+        .copied()
+        .filter(|route| match (&route.consumes, &content_type) {
+            (Some(consumes), Some(content_type)) => consumes
+                .essence()
+                .eq_ignore_ascii_case(&content_type.essence()),
+            _ => true,
+        })
+        .collect();
+
+    if routes.is_empty() {
+        return Err(StatusCode::UnsupportedMediaType);
+    }
+
+    let accept = match header(req, "accept") {
+        Some(accept) => accept
+            .split(',')
+            .filter_map(|range| {
+                let quality = range
+                    .split(';')
+                    .skip(1)
+                    .filter_map(|param| param.trim().strip_prefix("q="))
+                    .find_map(|q| f32::from_str(q.trim()).ok())
+                    .unwrap_or(1.0);
+
+                media_type(range).map(|range| (range, quality))
+            })
+            .collect(),
+        None => vec![(MediaType::any(), 1.0)],
+    };
+
+    let mut best: Option<(&Route, f32)> = None;
+
+    for route in &routes {
+        let produces = match &route.produces {
+            Some(produces) => produces,
+            None => continue,
+        };
+
+        let media_type = match media_type(&produces.to_string()) {
+            Some(media_type) => media_type,
+            None => continue,
+        };
+
+        let quality = accept
+            .iter()
+            .filter(|(range, _)| range.includes(&media_type))
+            .max_by_key(|(range, _)| range.specificity())
+            .map(|(_, quality)| *quality)
+            .unwrap_or(0.0);
+
+        if quality > 0.0 && best.is_none_or(|(_, best)| quality > best) {
+            best = Some((route, quality));
+        }
+    }
+
+    best.map(|(route, _)| route)
+        .or_else(|| {
+            routes
+                .iter()
+                .copied()
+                .find(|route| route.produces.is_none())
+        })
+        .ok_or(StatusCode::NotAcceptable)
+}
+
+/// Two routes negotiate alike when they consume and produce the same media types.
+pub(crate) fn same_media_types(route: &Route, other: &Route) -> bool {
+    let essence = |mime: &Option<Mime>| mime.as_ref().map(|mime| mime.essence().to_string());
+
+    essence(&route.consumes) == essence(&other.consumes)
+        && essence(&route.produces) == essence(&other.produces)
+}
+
+fn header<'r>(req: &'r http_types::Request, name: &str) -> Option<&'r str> {
+    req.header(&HeaderName::from_str(name).unwrap())
+        .and_then(|values| values.first())
+        .map(|value| value.as_str())
+}
+
+/// A media type or media range without its parameters, lowercased.
+struct MediaType {
+    basetype: String,
+    subtype: String,
+}
+
+impl MediaType {
+    fn any() -> Self {
+        Self {
+            basetype: "*".into(),
+            subtype: "*".into(),
+        }
+    }
+
+    fn essence(&self) -> String {
+        format!("{}/{}", self.basetype, self.subtype)
+    }
+
+    /// Whether the media range includes the media type.
+    fn includes(&self, media_type: &MediaType) -> bool {
+        (self.basetype == "*" || self.basetype == media_type.basetype)
+            && (self.subtype == "*" || self.subtype == media_type.subtype)
+    }
+
+    /// More specific media ranges take precedence over less specific ones.
+    fn specificity(&self) -> usize {
+        (self.basetype != "*") as usize + (self.subtype != "*") as usize
+    }
+}
+
+fn media_type(value: &str) -> Option<MediaType> {
+    let essence = value.split(';').next()?.trim().to_ascii_lowercase();
+    let mut parts = essence.splitn(2, '/');
+
+    let basetype = parts.next().filter(|part| !part.is_empty())?;
+    let subtype = parts.next().filter(|part| !part.is_empty())?;
+
+    Some(MediaType {
+        basetype: basetype.to_string(),
+        subtype: subtype.to_string(),
+    })
+}
@@ -0,0 +1,387 @@
+use crate::{
+    error::Error,
+    params::Params,
+    props::{Props, PropsFuture},
+    util::percent_decode,
+};
+use http_types::StatusCode;
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use std::fmt;
+
+/// Props that deserializes the query string of the request into `T`.
+///
+/// A key that is repeated, like `tag=a&tag=b`, or that ends with `[]`, like `tag[]=a&tag[]=b`,
+/// deserializes into a sequence.  A key with a name in brackets, like `filter[age]=30`,
+/// deserializes into a nested map or struct.  Keys and values are percent-decoded, with `+`
+/// decoded as a space.  An empty value deserializes into `None` for optional fields.
+///
+/// When the query string cannot be deserialized a `400 Bad Request` is returned, with a JSON body
+/// naming the offending field.
+///
+/// ## Examples
+/// ```
+/// # use windmill::*;
+/// # use http_types::{Method, Request, StatusCode, Url};
+/// # use serde::Deserialize;
+/// # use async_std::task;
+/// #[derive(Deserialize)]
+/// struct Search {
+///     q: String,
+///     page: Option<u32>,
+///     tags: Vec<String>,
+/// }
+///
+/// let url = Url::parse("http://localhost/search?q=rust+web&tags=async&tags=http").unwrap();
+/// let req = Request::new(Method::Get, url);
+/// let (_, _, query) = task::block_on(<Query<Search> as Props>::call(req, Params::new())).unwrap();
+///
+/// assert_eq!(query.inner.q, "rust web");
+/// assert_eq!(query.inner.page, None);
+/// assert_eq!(query.inner.tags, ["async", "http"]);
+///
+/// let url = Url::parse("http://localhost/search?q=rust&page=first").unwrap();
+/// let req = Request::new(Method::Get, url);
+/// let e = task::block_on(<Query<Search> as Props>::call(req, Params::new())).err().unwrap();
+///
+/// assert_eq!(e.code(), StatusCode::BadRequest);
+/// assert_eq!(e.msg()["field"], "page");
+/// ```
+pub struct Query<T> {
+    pub inner: T,
+}
+
+impl<T: DeserializeOwned + 'static> Props for Query<T> {
+    type Fut = PropsFuture<Self>;
+
+    fn call(req: http_types::Request, params: Params) -> Self::Fut {
+        Box::pin(async move {
+            let inner = from_query(req.url().query().unwrap_or("")).map_err(|e| Error {
+                code: StatusCode::BadRequest,
+                msg: serde_json::json!({
+                    "field": e.field,
+                    "message": e.message,
+                }),
+            })?;
+
+            Ok((req, params, Query { inner }))
+        })
+    }
+}
+
+/// Deserialize a query string into `T`.
+fn from_query<T: DeserializeOwned>(query: &str) -> Result<T, QueryError> {
+    let mut entries = vec![];
+
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let mut parts = pair.splitn(2, '=');
+        let key = decode(parts.next().unwrap_or(""), None)?;
+        let value = decode(parts.next().unwrap_or(""), Some(&key))?;
+
+        let (name, rest) = match key.find('[') {
+            Some(index) => key.split_at(index),
+            None => (key.as_str(), ""),
+        };
+
+        let mut path = vec![name];
+        let mut rest = rest;
+
+        while let Some(inner) = rest.strip_prefix('[') {
+            let end = inner.find(']').ok_or_else(|| QueryError {
+                field: Some(key.clone()),
+                message: "unclosed bracket in key".into(),
+            })?;
+
+            path.push(&inner[..end]);
+            rest = &inner[end + 1..];
+        }
+
+        if !rest.is_empty() {
+            return Err(QueryError {
+                field: Some(key.clone()),
+                message: "unexpected characters after bracket in key".into(),
+            });
+        }
+
+        insert(&mut entries, &path, value).map_err(|e| e.within(name))?;
+    }
+
+    T::deserialize(Value::Map(entries))
+}
+
+fn decode(raw: &str, field: Option<&str>) -> Result<String, QueryError> {
+    percent_decode(&raw.replace('+', " ")).ok_or_else(|| QueryError {
+        field: field.map(String::from),
+        message: format!("invalid percent-encoding in `{}`", raw),
+    })
+}
+
+/// Insert a value into the entries at the path of names parsed from its key.
+fn insert(
+    entries: &mut Vec<(String, Value)>,
+    path: &[&str],
+    value: String,
+) -> Result<(), QueryError> {
+    let (name, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return Ok(()),
+    };
+
+    let index = match entries.iter().position(|(key, _)| key == name) {
+        Some(index) => index,
+        None if rest.is_empty() => {
+            entries.push((name.to_string(), Value::String(value)));
+            return Ok(());
+        }
+        None => {
+            let entry = match rest {
+                [""] => Value::Seq(vec![]),
+                _ => Value::Map(vec![]),
+            };
+
+            entries.push((name.to_string(), entry));
+            entries.len() - 1
+        }
+    };
+
+    let entry = &mut entries[index].1;
+
+    if let (Value::String(_), [] | [""]) = (&*entry, rest) {
+        let first = std::mem::replace(entry, Value::Seq(vec![]));
+        *entry = Value::Seq(vec![first, Value::String(value)]);
+        return Ok(());
+    }
+
+    match (rest, entry) {
+        ([], Value::Seq(values)) | ([""], Value::Seq(values)) => {
+            values.push(Value::String(value));
+            Ok(())
+        }
+        ([name, ..], Value::Map(entries)) if !name.is_empty() => {
+            insert(entries, rest, value).map_err(|e| e.within(name))
+        }
+        _ => Err(QueryError {
+            field: None,
+            message: "a key is used both as a value and as a sequence or map".into(),
+        }),
+    }
+}
+
+/// A parsed query string, or a part of it.
+enum Value {
+    String(String),
+    Seq(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
+/// An error raised while deserializing a query string.
+#[derive(Debug)]
+struct QueryError {
+    field: Option<String>,
+    message: String,
+}
+
+impl QueryError {
+    /// Prefix the field of the error with the name of the key it was found in.
+    fn within(self, name: &str) -> Self {
+        let field = match self.field {
+            Some(field) => format!("{}[{}]", name, field),
+            None => name.to_string(),
+        };
+
+        Self {
+            field: Some(field),
+            message: self.message,
+        }
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.field {
+            Some(field) => write!(f, "{}: {}", field, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl de::Error for QueryError {
+    fn custom<M: fmt::Display>(msg: M) -> Self {
+        Self {
+            field: None,
+            message: msg.to_string(),
+        }
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        Self {
+            field: Some(field.to_string()),
+            message: "missing field".into(),
+        }
+    }
+
+    fn unknown_field(field: &str, _expected: &'static [&'static str]) -> Self {
+        Self {
+            field: Some(field.to_string()),
+            message: "unknown field".into(),
+        }
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                match self {
+                    Value::String(value) => match value.parse() {
+                        Ok(parsed) => visitor.$visit(parsed),
+                        Err(e) => Err(de::Error::custom(format!("invalid value `{}`: {}", value, e))),
+                    },
+                    value => value.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Value {
+    type Error = QueryError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            Value::String(value) => visitor.visit_string(value),
+            Value::Seq(values) => visitor.visit_seq(SeqAccess {
+                values: values.into_iter().enumerate(),
+            }),
+            Value::Map(entries) => visitor.visit_map(MapAccess {
+                entries: entries.into_iter(),
+                value: None,
+            }),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match &self {
+            Value::String(value) if value.is_empty() => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            Value::String(value) => Value::Seq(vec![Value::String(value)]).deserialize_any(visitor),
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self {
+            Value::String(value) => visitor.visit_enum(value.into_deserializer()),
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        str string bytes byte_buf unit_struct tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct SeqAccess {
+    values: std::iter::Enumerate<std::vec::IntoIter<Value>>,
+}
+
+impl<'de> de::SeqAccess<'de> for SeqAccess {
+    type Error = QueryError;
+
+    fn next_element_seed<S: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, Self::Error> {
+        match self.values.next() {
+            Some((index, value)) => seed.deserialize(value).map(Some).map_err(|e| QueryError {
+                field: Some(match e.field {
+                    Some(field) => format!("{}[{}]", index, field),
+                    None => index.to_string(),
+                }),
+                message: e.message,
+            }),
+            None => Ok(None),
+        }
+    }
+}
+
+struct MapAccess {
+    entries: std::vec::IntoIter<(String, Value)>,
+    value: Option<(String, Value)>,
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess {
+    type Error = QueryError;
+
+    fn next_key_seed<S: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, Self::Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                let deserialized = seed.deserialize(key.clone().into_deserializer())?;
+                self.value = Some((key, value));
+                Ok(Some(deserialized))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<S: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<S::Value, Self::Error> {
+        let (key, value) = self
+            .value
+            .take()
+            .ok_or_else(|| de::Error::custom("value requested before key"))?;
+
+        seed.deserialize(value).map_err(|e| e.within(&key))
+    }
+}
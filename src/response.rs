@@ -0,0 +1,138 @@
+use crate::error::Error;
+use http_types::{mime, Mime, StatusCode};
+use serde::Serialize;
+
+/// A trait for values an endpoint can return.
+///
+/// The [`#[endpoint]`](attr.endpoint.html) macro converts the value returned by an endpoint into
+/// the response sent to the client with this trait.
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use http_types::StatusCode;
+/// # use serde::Serialize;
+/// #[derive(Serialize)]
+/// struct User {
+///     name: String,
+/// }
+///
+/// #[endpoint]
+/// async fn hello() -> &'static str {
+///     "Hello!"
+/// }
+///
+/// #[endpoint]
+/// async fn create_user() -> (StatusCode, Json<User>) {
+///     (StatusCode::Created, Json(User { name: "bob".into() }))
+/// }
+///
+/// #[endpoint]
+/// async fn find_user() -> Option<Json<User>> {
+///     None
+/// }
+/// ```
+pub trait IntoResponse {
+    fn into_response(self) -> http_types::Response;
+}
+
+/// A value serialized as the JSON body of a response.
+pub struct Json<T>(pub T);
+
+impl IntoResponse for http_types::Response {
+    fn into_response(self) -> http_types::Response {
+        self
+    }
+}
+
+/// Responds with `200 OK` and the string as a `text/plain` body.
+impl IntoResponse for String {
+    fn into_response(self) -> http_types::Response {
+        response(StatusCode::Ok, self, mime::PLAIN)
+    }
+}
+
+/// Responds with `200 OK` and the string as a `text/plain` body.
+impl IntoResponse for &'static str {
+    fn into_response(self) -> http_types::Response {
+        response(StatusCode::Ok, self, mime::PLAIN)
+    }
+}
+
+/// Responds with `200 OK` and the bytes as an `application/octet-stream` body.
+impl IntoResponse for Vec<u8> {
+    fn into_response(self) -> http_types::Response {
+        response(StatusCode::Ok, self, mime::BYTE_STREAM)
+    }
+}
+
+/// Responds with the status code and an empty body.
+impl IntoResponse for StatusCode {
+    fn into_response(self) -> http_types::Response {
+        http_types::Response::new(self)
+    }
+}
+
+/// Responds with the response of the value, with its status code replaced.
+impl<T: IntoResponse> IntoResponse for (StatusCode, T) {
+    fn into_response(self) -> http_types::Response {
+        let (code, value) = self;
+        let mut res = value.into_response();
+        res.set_status(code);
+        res
+    }
+}
+
+/// Responds with `200 OK` and the value as a JSON body, or with `500 Internal Server Error` when
+/// the value cannot be serialized.
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> http_types::Response {
+        match serde_json::to_vec(&self.0) {
+            Ok(bytes) => response(StatusCode::Ok, bytes, mime::JSON),
+            Err(e) => Error {
+                code: StatusCode::InternalServerError,
+                msg: serde_json::json!(e.to_string()),
+            }
+            .into_response(),
+        }
+    }
+}
+
+impl<T: IntoResponse, E: IntoResponse> IntoResponse for Result<T, E> {
+    fn into_response(self) -> http_types::Response {
+        match self {
+            Ok(value) => value.into_response(),
+            Err(e) => e.into_response(),
+        }
+    }
+}
+
+/// Responds with the response of the value, or with `404 Not Found` for `None`.
+impl<T: IntoResponse> IntoResponse for Option<T> {
+    fn into_response(self) -> http_types::Response {
+        match self {
+            Some(value) => value.into_response(),
+            None => http_types::Response::new(StatusCode::NotFound),
+        }
+    }
+}
+
+/// Responds with the code of the error and its message as a JSON body.
+impl IntoResponse for Error {
+    fn into_response(self) -> http_types::Response {
+        let bytes = serde_json::to_vec(self.msg()).unwrap();
+        response(self.code(), bytes, mime::JSON)
+    }
+}
+
+fn response(
+    code: StatusCode,
+    body: impl Into<http_types::Body>,
+    mime: Mime,
+) -> http_types::Response {
+    let mut res = http_types::Response::new(code);
+    res.set_body(body);
+    let _ = res.set_content_type(mime);
+    res
+}
@@ -1,73 +1,367 @@
-use crate::params::Params;
-use std::{future::Future, pin::Pin};
+use crate::{
+    endpoint::Metadata,
+    error::Error,
+    middleware::Middleware,
+    params::Params,
+    util::{percent_decode, percent_encode},
+};
+use http_types::{Mime, StatusCode};
+use std::{borrow::Cow, collections::HashMap, fmt, future::Future, pin::Pin, sync::Arc};
 
-pub(crate) type ResponseFuture = Pin<Box<dyn Future<Output = http_types::Response> + Send + Sync>>;
-pub(crate) type RouteFn = Box<dyn Fn(http_types::Request, Params) -> ResponseFuture + Send + Sync>;
+pub type ResponseFuture = Pin<Box<dyn Future<Output = http_types::Response> + Send + Sync>>;
+pub(crate) type RouteFn = Arc<dyn Fn(http_types::Request, Params) -> ResponseFuture + Send + Sync>;
 
 /// A route constructed using the [`route!`](macro.route.html) macro.  
 pub struct Route {
     pub static_segments: Vec<StaticSegment>,
     pub dynamic_segments: Vec<DynamicSegment>,
+    pub catch_all: Option<DynamicSegment>,
+    pub optional_positions: Vec<usize>,
+    pub produces: Option<Mime>,
+    pub consumes: Option<Mime>,
     pub handler: Option<RouteFn>,
+    pub middleware: Vec<Arc<dyn Middleware>>,
+    pub name: Option<&'static str>,
+    pub metadata: Option<Metadata>,
+}
+
+impl Route {
+    /// A route without any segments.
+    pub(crate) fn root() -> Self {
+        Route {
+            static_segments: vec![],
+            dynamic_segments: vec![],
+            catch_all: None,
+            optional_positions: vec![],
+            produces: None,
+            consumes: None,
+            handler: None,
+            middleware: vec![],
+            name: None,
+            metadata: None,
+        }
+    }
+
+    /// Wrap the endpoint of this route in a middleware.  Route middleware runs after the
+    /// middleware registered on the router, in the order it was added.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use http_types::{Method, Response, StatusCode};
+    /// # #[endpoint] async fn admin() -> Result<Response, Error> { Ok(Response::new(StatusCode::Ok)) }
+    /// fn deny(_req: http_types::Request, _params: Params, _next: Next) -> ResponseFuture {
+    ///     Box::pin(async { Response::new(StatusCode::Forbidden) })
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"admin").middleware(deny), admin);
+    /// ```
+    pub fn middleware(mut self, middleware: impl Middleware) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Declare the media type of the responses of this route, so it is only picked for requests
+    /// that accept it.  See [content negotiation](struct.Router.html#content-negotiation).  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use http_types::{mime, Method, Response, StatusCode};
+    /// # #[endpoint] async fn report_json() -> Result<Response, Error> { Ok(Response::new(StatusCode::Ok)) }
+    /// # #[endpoint] async fn report_html() -> Result<Response, Error> { Ok(Response::new(StatusCode::Ok)) }
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"reports"/id).produces(mime::JSON), report_json);
+    /// router.add(Method::Get, route!(/"reports"/id).produces(mime::HTML), report_html);
+    /// ```
+    pub fn produces(mut self, mime: Mime) -> Self {
+        self.produces = Some(mime);
+        self
+    }
+
+    /// Declare the media type of the request bodies this route accepts, so it is only picked for
+    /// requests with a matching `Content-Type`.  See
+    /// [content negotiation](struct.Router.html#content-negotiation).  
+    pub fn consumes(mut self, mime: Mime) -> Self {
+        self.consumes = Some(mime);
+        self
+    }
+}
+
+/// Formats the route as a pattern, with every dynamic segment in braces.  
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// assert_eq!(route!(/"users"/id: u64/"posts").to_string(), "/users/{id: u64}/posts");
+/// assert_eq!(route!(/"static"/path..).to_string(), "/static/{path..}");
+/// assert_eq!(route!(/"list"/["page"/page]).to_string(), "/list[/page/{page}]");
+/// ```
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (position, segment) in self.segments().into_iter().enumerate() {
+            if self.optional_positions.contains(&position) {
+                write!(f, "[")?;
+            }
+
+            match segment {
+                Segment::Static(static_segment) => write!(f, "/{}", static_segment.value)?,
+                Segment::Dynamic(dynamic_segment) => match &dynamic_segment.constraint {
+                    Some(Constraint::Type(ty, _)) => {
+                        write!(f, "/{{{}: {}}}", dynamic_segment.name, ty)?
+                    }
+                    Some(Constraint::Pattern(pattern)) => {
+                        write!(f, "/{{{} ~ {}}}", dynamic_segment.name, pattern)?
+                    }
+                    None => write!(f, "/{{{}}}", dynamic_segment.name)?,
+                },
+            }
+        }
+
+        if let Some(catch_all) = &self.catch_all {
+            write!(f, "/{{{}..}}", catch_all.name)?;
+        }
+
+        for _ in &self.optional_positions {
+            write!(f, "]")?;
+        }
+
+        if self.len() == 0 {
+            write!(f, "/")?;
+        }
+
+        Ok(())
+    }
 }
 
 #[doc(hidden)]
+#[derive(Clone)]
 pub struct StaticSegment {
     pub value: &'static str,
     pub position: usize,
 }
 
 #[doc(hidden)]
+#[derive(Clone)]
 pub struct DynamicSegment {
     pub name: &'static str,
     pub position: usize,
+    pub constraint: Option<Constraint>,
 }
 
-pub(crate) struct RawSegment<'s> {
-    pub(crate) value: &'s str,
-    position: usize,
+/// A constraint a dynamic segment must satisfy for the route to match.  
+#[doc(hidden)]
+#[derive(Clone)]
+pub enum Constraint {
+    /// The segment must parse as the named type.  
+    Type(&'static str, fn(&str) -> bool),
+    /// The whole segment must match the regular expression.  
+    Pattern(&'static str),
 }
 
-pub(crate) struct RawRoute<'s> {
-    pub raw_segments: Vec<RawSegment<'s>>,
+pub(crate) enum Segment<'r> {
+    Static(&'r StaticSegment),
+    Dynamic(&'r DynamicSegment),
 }
 
-impl<'s> RawRoute<'s> {
-    pub(crate) fn from_path(path: &'s str) -> Self {
-        Self {
-            raw_segments: path
-                .split("/")
-                .skip(1)
-                .enumerate()
-                .map(|(i, segment)| RawSegment {
-                    value: segment,
-                    position: i,
+impl Route {
+    /// The segments of the route ordered by their position in the path.
+    pub(crate) fn segments(&self) -> Vec<Segment<'_>> {
+        let mut segments: Vec<(usize, Segment)> = self
+            .static_segments
+            .iter()
+            .map(|segment| (segment.position, Segment::Static(segment)))
+            .chain(
+                self.dynamic_segments
+                    .iter()
+                    .map(|segment| (segment.position, Segment::Dynamic(segment))),
+            )
+            .collect();
+
+        segments.sort_by_key(|(position, _)| *position);
+        segments.into_iter().map(|(_, segment)| segment).collect()
+    }
+
+    /// The number of segments in the route.
+    pub(crate) fn len(&self) -> usize {
+        self.static_segments.len() + self.dynamic_segments.len() + self.catch_all.iter().count()
+    }
+
+    /// The numbers of segments a path may have to match the route, ignoring a catch-all.  A route
+    /// with optional segments matches at the start of every optional group and with all of its
+    /// segments.
+    pub(crate) fn depths(&self) -> Vec<usize> {
+        let mut depths = self.optional_positions.clone();
+        depths.push(self.static_segments.len() + self.dynamic_segments.len());
+        depths.sort();
+        depths.dedup();
+        depths
+    }
+
+    /// Move the route below `prefix`, shifting the position of every segment past the segments of
+    /// the prefix.  The middleware of the prefix runs before the middleware of the route.
+    pub(crate) fn prefixed(mut self, prefix: &Route) -> Route {
+        let offset = prefix.len();
+
+        self.static_segments
+            .iter_mut()
+            .for_each(|segment| segment.position += offset);
+        self.dynamic_segments
+            .iter_mut()
+            .chain(self.catch_all.iter_mut())
+            .for_each(|segment| segment.position += offset);
+        self.optional_positions
+            .iter_mut()
+            .for_each(|position| *position += offset);
+
+        self.static_segments
+            .splice(0..0, prefix.static_segments.iter().cloned());
+        self.dynamic_segments
+            .splice(0..0, prefix.dynamic_segments.iter().cloned());
+        self.middleware
+            .splice(0..0, prefix.middleware.iter().cloned());
+
+        self
+    }
+
+    /// Build a percent-encoded path for the route, taking the value of every dynamic segment from
+    /// `params`.  Returns `None` if a value is missing.  An optional group is only included when
+    /// all of its values are given and it captures at least one of them.
+    pub(crate) fn url(&self, params: &[(&str, &str)]) -> Option<String> {
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| *value)
+        };
+
+        let segments = self.segments();
+        let depths = self.depths();
+
+        let mut depth = 0;
+        let starts = std::iter::once(0).chain(depths.iter().copied());
+        for (index, (start, end)) in starts.zip(&depths).enumerate() {
+            let group = &segments[start..*end];
+            let dynamics = group
+                .iter()
+                .filter_map(|segment| match segment {
+                    Segment::Dynamic(dynamic_segment) => Some(dynamic_segment.name),
+                    Segment::Static(_) => None,
                 })
-                .collect(),
+                .collect::<Vec<_>>();
+
+            if dynamics.iter().all(|name| param(name).is_some()) {
+                if index == 0 || !dynamics.is_empty() {
+                    depth = *end;
+                }
+            } else if index == 0 {
+                return None;
+            } else {
+                break;
+            }
         }
+
+        let mut url = String::new();
+
+        for segment in &segments[..depth] {
+            url.push('/');
+            match segment {
+                Segment::Static(static_segment) => {
+                    url.push_str(&percent_encode(static_segment.value))
+                }
+                Segment::Dynamic(dynamic_segment) => {
+                    url.push_str(&percent_encode(param(dynamic_segment.name)?))
+                }
+            }
+        }
+
+        if let Some(catch_all) = &self.catch_all {
+            let rest: Vec<String> = param(catch_all.name)?
+                .split('/')
+                .map(percent_encode)
+                .collect();
+
+            url.push('/');
+            url.push_str(&rest.join("/"));
+        }
+
+        if url.is_empty() {
+            url.push('/');
+        }
+
+        Some(url)
     }
-}
 
-impl<'s> PartialEq<RawSegment<'s>> for StaticSegment {
-    fn eq(&self, other: &RawSegment) -> bool {
-        self.position == other.position && self.value == other.value
+    /// Capture the dynamic segments of a matching raw route.  Optional segments missing from the
+    /// raw route are left out.
+    pub(crate) fn params(&self, raw_route: &RawRoute) -> Params {
+        let mut params = HashMap::new();
+
+        self.dynamic_segments
+            .iter()
+            .filter(|dynamic_segment| dynamic_segment.position < raw_route.raw_segments.len())
+            .for_each(|dynamic_segment| {
+                params.insert(
+                    dynamic_segment.name,
+                    raw_route.raw_segments[dynamic_segment.position]
+                        .value
+                        .to_string(),
+                );
+            });
+
+        if let Some(catch_all) = &self.catch_all {
+            let rest: Vec<&str> = raw_route.raw_segments[catch_all.position..]
+                .iter()
+                .map(|raw_segment| raw_segment.value.as_ref())
+                .collect();
+
+            params.insert(catch_all.name, rest.join("/"));
+        }
+
+        params
     }
 }
 
-impl<'s> PartialEq<RawSegment<'s>> for DynamicSegment {
-    fn eq(&self, other: &RawSegment) -> bool {
-        self.position == other.position
-    }
+pub(crate) struct RawSegment<'s> {
+    pub(crate) value: Cow<'s, str>,
 }
 
-impl<'s> PartialEq<StaticSegment> for RawSegment<'s> {
-    fn eq(&self, other: &StaticSegment) -> bool {
-        other == self
-    }
+pub(crate) struct RawRoute<'s> {
+    pub raw_segments: Vec<RawSegment<'s>>,
 }
 
-impl<'s> PartialEq<DynamicSegment> for RawSegment<'s> {
-    fn eq(&self, other: &DynamicSegment) -> bool {
-        other == self
+impl<'s> RawRoute<'s> {
+    /// Split a path into its segments and percent-decode each of them.  The path is split before
+    /// decoding, so an encoded slash never separates two segments.  Fails with `400 Bad Request`
+    /// when a segment is not valid percent-encoded UTF-8.
+    pub(crate) fn from_path(path: &'s str) -> Result<Self, Error> {
+        if path == "/" {
+            return Ok(Self {
+                raw_segments: vec![],
+            });
+        }
+
+        let raw_segments = path
+            .split("/")
+            .skip(1)
+            .map(|segment| {
+                let value = if segment.contains('%') {
+                    Cow::Owned(percent_decode(segment).ok_or_else(|| Error {
+                        code: StatusCode::BadRequest,
+                        msg: serde_json::json!(format!("invalid path segment `{}`", segment)),
+                    })?)
+                } else {
+                    Cow::Borrowed(segment)
+                };
+
+                Ok(RawSegment { value })
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(Self { raw_segments })
     }
 }
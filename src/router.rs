@@ -1,17 +1,156 @@
 use crate::{
-    endpoint::Endpoint,
+    endpoint::{Endpoint, Metadata},
+    error::{Error, RouteError},
+    host::{request_host, HostPattern},
+    middleware::{Guard, Middleware, Next},
+    negotiation::negotiate,
     params::Params,
-    route::{RawRoute, ResponseFuture, Route},
+    props::Props,
+    response::IntoResponse,
+    route::{RawRoute, ResponseFuture, Route, RouteFn},
+    tree::Tree,
+    util::normalize_path,
 };
-use http_types::{mime, Method, Mime, StatusCode};
-use std::{collections::HashMap, future::Future, sync::Arc};
+use async_std::io;
+use http_types::{headers::HeaderName, Body, Method, StatusCode};
+use std::{collections::HashMap, fmt, future::Future, iter, str::FromStr, sync::Arc};
 
 /// The router for routing requests.  
 ///
 /// A route in the router is composed of an `http-types::Method`, a
 /// [`Route`](struct.Route.html), and an endpoint.  
+///
+/// When no route matches the request path a `404 Not Found` is returned.  When the path matches a
+/// route registered under a different method a `405 Method Not Allowed` is returned instead, with
+/// an `Allow` header listing every method registered for the path.  
+///
+/// `HEAD` requests without a `HEAD` route are answered by running the matching `GET` route and
+/// dropping the body of its response, the `Content-Length` is kept.  `OPTIONS` requests without an
+/// `OPTIONS` route are answered with `204 No Content` and an `Allow` header.  Routes added
+/// explicitly for `HEAD` or `OPTIONS` always take priority over this behaviour.  
+///
+/// ## Percent-encoding
+///
+/// The request path is split into segments before any segment is percent-decoded, so an encoded
+/// slash (`%2F`) never separates two segments and encoded dots (`%2E`) are never resolved by path
+/// normalization.  Static segments are compared with the decoded segment, so `route!(/"a b")`
+/// matches `/a%20b`, and dynamic segments capture the decoded segment.  A request with a segment
+/// containing a malformed escape or decoding to invalid UTF-8 is answered with
+/// `400 Bad Request` before any endpoint runs.  
+///
+/// ## Content negotiation
+///
+/// Several routes can be added with the same method and path when they differ by the media types
+/// they [consume](struct.Route.html#method.consumes) or
+/// [produce](struct.Route.html#method.produces).  The routes that consume the `Content-Type` of
+/// the request are kept, or `415 Unsupported Media Type` is returned.  Of those, the route
+/// producing the media type the `Accept` header prefers is used, taking quality values and
+/// wildcards into account.  A route that declares no media type is used when no other route is
+/// acceptable, otherwise `406 Not Acceptable` is returned.  A request without an `Accept` header
+/// accepts any media type, and ties go to the route added first.  
 pub struct Router {
-    table: HashMap<Method, Vec<Route>>,
+    table: HashMap<Method, Tree>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    fallbacks: Tree,
+    path_policy: PathPolicy,
+    hosts: Vec<(HostPattern, Arc<Router>)>,
+}
+
+/// A route of a router, as returned by [`Router::routes`](struct.Router.html#method.routes).  
+pub struct RouteInfo<'r> {
+    method: Method,
+    route: &'r Route,
+    host: Option<&'static str>,
+}
+
+impl<'r> RouteInfo<'r> {
+    /// The method of the route.  
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// The pattern of the route, see the `Display` impl of [`Route`](struct.Route.html).  
+    pub fn pattern(&self) -> String {
+        self.route.to_string()
+    }
+
+    /// The pattern of the host the route is served on, if it belongs to a
+    /// [host router](struct.Router.html#method.host).  
+    pub fn host(&self) -> Option<&'static str> {
+        self.host
+    }
+
+    /// The name of the route, if it was added with a name.  
+    pub fn name(&self) -> Option<&'static str> {
+        self.route.name
+    }
+
+    /// The metadata of the endpoint of the route.  
+    pub fn metadata(&self) -> Option<&'r Metadata> {
+        self.route.metadata.as_ref()
+    }
+}
+
+/// Formats the route table, one route per line.  Routes of host routers are prefixed with the
+/// pattern of their host.  
+///
+/// ## Examples
+/// ```
+/// # #![feature(proc_macro_hygiene)]
+/// # use windmill::*;
+/// # use http_types::{Method, Response, StatusCode};
+/// # #[endpoint] async fn user() -> Result<Response, Error> { Ok(Response::new(StatusCode::Ok)) }
+/// let mut api = Router::new();
+/// api.add(Method::Get, route!(/"status"), user);
+///
+/// let mut router = Router::new();
+/// router.add(Method::Post, route!(/"users"), user);
+/// router.add_named("user_show", Method::Get, route!(/"users"/id), user);
+/// router.host("api.example.com", api);
+///
+/// assert_eq!(
+///     router.to_string(),
+///     "POST /users\nGET /users/{id} (user_show)\nGET api.example.com/status\n"
+/// );
+/// ```
+impl fmt::Display for Router {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for route in self.routes() {
+            write!(f, "{} ", route.method())?;
+
+            if let Some(host) = route.host() {
+                write!(f, "{}", host)?;
+            }
+
+            write!(f, "{}", route.pattern())?;
+
+            if let Some(name) = route.name() {
+                write!(f, " ({})", name)?;
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// How the router treats request paths that are not in their canonical form.  
+///
+/// A path is canonical when it has no trailing slash, no repeated slashes and no `.` or `..`
+/// segments, see [`normalize_path`](fn.normalize_path.html).  The root path `/` is canonical.  
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PathPolicy {
+    /// Match the path exactly as it was requested, so `/hello/bob/` does not match
+    /// `route!(/"hello"/name)`.  This is the default.  
+    Strict,
+    /// Redirect requests for a non-canonical path to the canonical path when a route matches it,
+    /// with `301 Moved Permanently` for `GET` and `HEAD` requests and `308 Permanent Redirect`
+    /// for every other method.  The query string is kept.  
+    Redirect,
+    /// Match the canonical form of the path, so `/hello/bob/` and `//hello/bob` are handled as if
+    /// `/hello/bob` was requested.  
+    Lenient,
 }
 
 impl Router {
@@ -25,9 +164,26 @@ impl Router {
     pub fn new() -> Self {
         Router {
             table: HashMap::new(),
+            middleware: vec![],
+            fallbacks: Tree::new(),
+            path_policy: PathPolicy::Strict,
+            hosts: vec![],
         }
     }
 
+    /// Set how the router treats request paths that are not in their canonical form.  Paths are
+    /// normalized before matching, so the policy of a mounted router has no effect.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # use windmill::*;
+    /// let mut router = Router::new();
+    /// router.path_policy(PathPolicy::Redirect);
+    /// ```
+    pub fn path_policy(&mut self, policy: PathPolicy) {
+        self.path_policy = policy;
+    }
+
     /// ## Examples
     /// ```
     /// # #![feature(proc_macro_hygiene)]
@@ -46,13 +202,14 @@ impl Router {
     ///
     /// let mut router = Router::new();
     ///
-    /// router.add(Method::Get, route!(/"example"), ___example);
-    /// router.add(Method::Get, route!(/"example2"), ___example2);
+    /// router.add(Method::Get, route!(/"example"), example);
+    /// router.add(Method::Get, route!(/"example2"), example2);
     /// ```
     ///
-    /// ## Precedence and ambiguity
+    /// ## Precedence
     ///
-    /// When adding routes to the router the order they are added in is sometimes important.  
+    /// Static segments always take precedence over dynamic segments, regardless of the order the
+    /// routes are added in.  
     /// ```
     /// # #![feature(proc_macro_hygiene)]
     /// # use windmill::*;
@@ -60,110 +217,1388 @@ impl Router {
     /// # let mut router = Router::new();
     /// # #[endpoint] async fn example() -> Result<Response, Error> { Ok(Response::from("greetings")) }
     /// # #[endpoint] async fn example2() -> Result<Response, Error> { Ok(Response::new(StatusCode::Ok)) }
-    /// router.add(Method::Get, route!(/a/b/c), ___example);
-    /// router.add(Method::Get, route!(/"a"/b/c), ___example2);
+    /// router.add(Method::Get, route!(/a/b/c), example);
+    /// router.add(Method::Get, route!(/"a"/b/c), example2);
     /// ```
-    /// In the example above the second route will never get run because the first route matches
-    /// the literal `"a"` as a dynamic segment.  To solve this simply insert _more specific_
-    /// routes before _less specific_ routes as seen in the example below.  
+    /// In the example above a request for `/a/b/c` is handled by `example2` because the literal
+    /// `"a"` is more specific than the dynamic segment `a`, while a request for `/z/b/c` is
+    /// handled by `example`.  
+    ///
+    /// Routes are stored in a segment tree, so the cost of a lookup depends on the number of
+    /// segments in the request path and not on the number of routes in the router.  
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the route conflicts with a route that was already added, see
+    /// [`try_add`](#method.try_add).  
+    pub fn add(&mut self, method: Method, route: Route, endpoint: impl Endpoint + Send + Sync) {
+        if let Err(e) = self.try_add(method, route, endpoint) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Add a route, returning an error if it conflicts with a route that was already added.  
+    ///
+    /// A route conflicts with another route when both have the same method and the same shape,
+    /// that is the same static segments and dynamic segments with the same constraints in the same
+    /// positions, and the same [media types](#content-negotiation), in which case the new route
+    /// could never match.  Named routes also conflict when their names are the same.  Constrained
+    /// segments are not compared with each other, when two constraints accept the same segment the
+    /// route added first wins.  
+    ///
+    /// ## Examples
     /// ```
     /// # #![feature(proc_macro_hygiene)]
     /// # use windmill::*;
     /// # use http_types::{Method, Response, StatusCode};
-    /// # let mut router = Router::new();
-    /// # #[endpoint] async fn example() -> Result<Response, Error> { Ok(Response::from("greetings")) }
-    /// # #[endpoint] async fn example2() -> Result<Response, Error> { Ok(Response::new(StatusCode::Ok)) }
-    /// router.add(Method::Get, route!(/"a"/b/c), ___example2);
-    /// router.add(Method::Get, route!(/a/b/c), ___example);
+    /// # #[endpoint] async fn user() -> Result<Response, Error> { Ok(Response::new(StatusCode::Ok)) }
+    /// let mut router = Router::new();
+    /// router.add(Method::Get, route!(/"users"/id), user);
+    ///
+    /// assert!(router.try_add(Method::Get, route!(/"users"/user_id), user).is_err());
+    /// assert!(router.try_add(Method::Get, route!(/"users"/id: u64), user).is_ok());
+    /// assert!(router.try_add(Method::Post, route!(/"users"/id), user).is_ok());
+    /// ```
+    pub fn try_add(
+        &mut self,
+        method: Method,
+        mut route: Route,
+        endpoint: impl Endpoint + Send + Sync,
+    ) -> Result<(), RouteError> {
+        route.metadata = Some(endpoint.metadata());
+        route.handler = Some(handler(endpoint));
+        self.insert(method, route)
+    }
+
+    /// Set the endpoint that handles requests which match no route under any method, instead of
+    /// the default empty `404 Not Found`.  
+    ///
+    /// The fallback of a [mounted](#method.mount) router handles the requests below its mount
+    /// point, so different sections of an application can have their own fallback.  When several
+    /// fallbacks match a request the one mounted deepest is used.  A mounted fallback is wrapped in
+    /// the middleware and guards of its router, like the routes of that router.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use http_types::{Method, Response, StatusCode};
+    /// # #[endpoint] async fn api_not_found() -> Result<Response, Error> { Ok(Response::new(StatusCode::NotFound)) }
+    /// # #[endpoint] async fn index() -> Result<Response, Error> { Ok(Response::from("<html></html>")) }
+    /// let mut api = Router::new();
+    /// api.fallback(api_not_found);
+    ///
+    /// let mut router = Router::new();
+    /// router.mount(route!(/"api"), api);
+    /// router.fallback(index);
+    /// ```
+    /// Above, a request for `/api/unknown` is handled by `api_not_found` while every other unknown
+    /// path is handled by `index`.  
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the router already has a fallback.  
+    pub fn fallback(&mut self, endpoint: impl Endpoint + Send + Sync) {
+        let mut route = Route::root();
+        route.handler = Some(handler(endpoint));
+
+        if self.fallbacks.insert(route).is_err() {
+            panic!("the router already has a fallback");
+        }
+    }
+
+    /// Add the endpoints collected by the [`routes!`](macro.routes.html) macro, each with the
+    /// method and route given to its [`#[endpoint]`](attr.endpoint.html) attribute.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use http_types::{Response, StatusCode};
+    /// #[endpoint(GET, /"hello"/name)]
+    /// async fn hello() -> Result<Response, Error> {
+    ///     Ok(Response::from("hello"))
+    /// }
+    ///
+    /// #[endpoint(POST, /"users")]
+    /// async fn create_user() -> Result<Response, Error> {
+    ///     Ok(Response::new(StatusCode::Created))
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.register(routes![hello, create_user]);
+    ///
+    /// assert_eq!(router.to_string(), "GET /hello/{name}\nPOST /users\n");
+    /// ```
+    ///
+    /// ## Panics
+    ///
+    /// Panics if a route conflicts with a route that was already added.  
+    pub fn register(&mut self, routes: impl FnOnce(&mut Router)) {
+        routes(self);
+    }
+
+    /// Add a route with a name that can be used to build URLs for it with
+    /// [`url_for`](#method.url_for).  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use http_types::{Method, Response, StatusCode};
+    /// # #[endpoint] async fn user() -> Result<Response, Error> { Ok(Response::new(StatusCode::Ok)) }
+    /// let mut router = Router::new();
+    /// router.add_named("user_show", Method::Get, route!(/"users"/id), user);
+    /// ```
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the route or its name conflicts with a route that was already added.  
+    pub fn add_named(
+        &mut self,
+        name: &'static str,
+        method: Method,
+        mut route: Route,
+        endpoint: impl Endpoint + Send + Sync,
+    ) {
+        route.name = Some(name);
+        self.add(method, route, endpoint);
+    }
+
+    /// Build the path of a named route, taking the value of every dynamic segment from `params`.  
+    /// Every segment of the path is percent-encoded.  Routes of [host routers](#method.host) are
+    /// found too, their path is built without the host.  Returns `None` if no route has the name
+    /// or if the value of a dynamic segment is missing.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use http_types::{Method, Response, StatusCode};
+    /// # #[endpoint] async fn user() -> Result<Response, Error> { Ok(Response::new(StatusCode::Ok)) }
+    /// let mut router = Router::new();
+    /// router.add_named("user_show", Method::Get, route!(/"users"/id), user);
+    ///
+    /// assert_eq!(router.url_for("user_show", &[("id", "42")]).unwrap(), "/users/42");
+    /// assert_eq!(router.url_for("user_show", &[("id", "John Doe")]).unwrap(), "/users/John%20Doe");
+    /// assert_eq!(router.url_for("user_show", &[]), None);
+    /// ```
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Option<String> {
+        self.named(name).and_then(|route| route.url(params))
+    }
+
+    /// The routes of the router, sorted by pattern and then by method, followed by the routes of
+    /// every [host router](#method.host) in the order they were added.  Routes answered
+    /// automatically, like `HEAD` routes for `GET` routes, are not included.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use http_types::{Method, Response, StatusCode};
+    /// # #[endpoint] async fn user() -> Result<Response, Error> { Ok(Response::new(StatusCode::Ok)) }
+    /// let mut router = Router::new();
+    /// router.add(Method::Post, route!(/"users"), user);
+    /// router.add_named("user_show", Method::Get, route!(/"users"/id: u64), user);
+    ///
+    /// let routes: Vec<String> = router
+    ///     .routes()
+    ///     .map(|route| format!("{} {} {:?}", route.method(), route.pattern(), route.name()))
+    ///     .collect();
+    ///
+    /// assert_eq!(routes, ["POST /users None", "GET /users/{id: u64} Some(\"user_show\")"]);
     /// ```
-    pub fn add(&mut self, method: Method, mut route: Route, endpoint: impl Endpoint + Send + Sync) {
-        let entry = self
+    pub fn routes(&self) -> impl Iterator<Item = RouteInfo<'_>> {
+        let mut routes = self.own_routes(None);
+
+        for (pattern, router) in &self.hosts {
+            routes.extend(router.own_routes(Some(pattern.as_str())));
+        }
+
+        routes.into_iter()
+    }
+
+    /// The routes of this router without those of its host routers, sorted by pattern and then by
+    /// method.
+    fn own_routes(&self, host: Option<&'static str>) -> Vec<RouteInfo<'_>> {
+        let mut routes: Vec<RouteInfo> = self
             .table
-            .entry(method)
-            .or_insert_with(|| Vec::<Route>::new());
+            .iter()
+            .flat_map(|(method, tree)| {
+                tree.routes.iter().map(move |route| RouteInfo {
+                    method: *method,
+                    route,
+                    host,
+                })
+            })
+            .collect();
 
-        let handler = move |req: http_types::Request, params: Params| -> ResponseFuture {
-            Box::pin(async move {
-                match endpoint.call(req, params).await {
-                    Ok(res) => res,
-                    Err(e) => {
-                        let mut res = response(e.code(), mime::JSON);
-                        let bytes = serde_json::to_vec(e.msg()).unwrap();
-                        res.set_body(bytes);
-                        res
-                    }
+        routes.sort_by_cached_key(|info| (info.pattern(), info.method.to_string()));
+        routes
+    }
+
+    /// The route with the name, among the routes of this router and of its host routers.
+    fn named(&self, name: &str) -> Option<&Route> {
+        iter::once(self)
+            .chain(self.hosts.iter().map(|(_, router)| &**router))
+            .flat_map(|router| router.table.values())
+            .flat_map(|tree| tree.routes.iter())
+            .find(|route| route.name == Some(name))
+    }
+
+    /// Wrap every response of the router in a middleware.  Middleware runs in the order it was
+    /// added, before any middleware added to the route itself.  It runs for every request the
+    /// router answers, including redirects, fallbacks and the error responses the router gives
+    /// when no route matches, so it can log or add headers to all of them.  It is handed the params
+    /// of the matching route, if any.  
+    ///
+    /// The middleware of a [mounted](#method.mount) router only wraps the routes of that router.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # use windmill::*;
+    /// # use http_types::headers::HeaderName;
+    /// # use std::str::FromStr;
+    /// fn powered_by(req: http_types::Request, params: Params, next: Next) -> ResponseFuture {
+    ///     Box::pin(async move {
+    ///         let mut res = next.run(req, params).await;
+    ///         let _ = res.insert_header(HeaderName::from_str("x-powered-by").unwrap(), "windmill");
+    ///         res
+    ///     })
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.middleware(powered_by);
+    /// ```
+    pub fn middleware(&mut self, middleware: impl Middleware) {
+        self.middleware.push(Arc::new(middleware));
+    }
+
+    /// Mount every route of `router` below `prefix`.  
+    ///
+    /// The routes of the mounted router keep their segments relative to the mount point, and any
+    /// dynamic segments of the prefix are captured into the params of the mounted routes.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use http_types::{Method, Response, StatusCode};
+    /// # #[endpoint] async fn user() -> Result<Response, Error> { Ok(Response::new(StatusCode::Ok)) }
+    /// let mut users = Router::new();
+    /// users.add(Method::Get, route!(/"users"/id), user);
+    ///
+    /// let mut router = Router::new();
+    /// router.mount(route!(/"tenants"/tenant_id), users);
+    /// ```
+    /// A request for `/tenants/7/users/42` is handled by `user` with both `tenant_id` and `id` in
+    /// its params.  The middleware of the mounted router keeps wrapping its own routes only, while
+    /// middleware added to the prefix wraps every mounted route ahead of it.  Its
+    /// [fallback](#method.fallback) handles the requests below the mount point.  
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the prefix has a name or media types or ends with a catch-all segment or optional
+    /// segments, if `router` has [host routers](#method.host), if a mounted route conflicts with a
+    /// route that was already added, or if a fallback was already mounted at the same prefix.  
+    pub fn mount(&mut self, prefix: Route, router: Router) {
+        assert!(
+            prefix.catch_all.is_none(),
+            "a router cannot be mounted below a catch-all segment"
+        );
+        assert!(
+            prefix.optional_positions.is_empty(),
+            "a router cannot be mounted below optional segments"
+        );
+        assert!(
+            prefix.name.is_none() && prefix.produces.is_none() && prefix.consumes.is_none(),
+            "a mount prefix cannot have a name or media types"
+        );
+        assert!(
+            router.hosts.is_empty(),
+            "a router with host routers cannot be mounted"
+        );
+
+        for (method, tree) in router.table {
+            for mut route in tree.routes {
+                route
+                    .middleware
+                    .splice(0..0, router.middleware.iter().cloned());
+
+                if let Err(e) = self.insert(method, route.prefixed(&prefix)) {
+                    panic!("{}", e);
                 }
+            }
+        }
+
+        for mut route in router.fallbacks.routes {
+            route
+                .middleware
+                .splice(0..0, router.middleware.iter().cloned());
+
+            if let Err((route, _)) = self.fallbacks.insert(route.prefixed(&prefix)) {
+                panic!("a fallback is already mounted at {}", route);
+            }
+        }
+    }
+
+    /// Add a group of routes sharing a path prefix, middleware and guards.  
+    ///
+    /// The closure receives an empty router that the routes of the group are added to.  Any
+    /// middleware or guard added to that router applies to every route of the group, and the
+    /// group is then [mounted](#method.mount) below `prefix`, so middleware added to `prefix`
+    /// wraps the whole group.  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use http_types::{Method, Response, StatusCode};
+    /// # struct Auth;
+    /// # impl Props for Auth {
+    /// #     type Fut = PropsFuture<Self>;
+    /// #     fn call(req: http_types::Request, params: Params) -> Self::Fut {
+    /// #         Box::pin(async move { Ok((req, params, Auth)) })
+    /// #     }
+    /// # }
+    /// # #[endpoint] async fn users() -> Result<Response, Error> { Ok(Response::new(StatusCode::Ok)) }
+    /// # #[endpoint] async fn settings() -> Result<Response, Error> { Ok(Response::new(StatusCode::Ok)) }
+    /// let mut router = Router::new();
+    ///
+    /// router.group(route!(/"admin"), |admin| {
+    ///     admin.guard::<Auth>();
+    ///     admin.add(Method::Get, route!(/"users"), users);
+    ///     admin.add(Method::Get, route!(/"settings"), settings);
+    /// });
+    /// ```
+    pub fn group(&mut self, prefix: Route, group: impl FnOnce(&mut Router)) {
+        let mut router = Router::new();
+        group(&mut router);
+        self.mount(prefix, router);
+    }
+
+    /// Require the props `P` to be constructed successfully before any endpoint of the router
+    /// runs.  The props is dropped once it has been constructed, when construction fails the
+    /// error is returned as the response and the endpoint never runs.  Guards run as middleware,
+    /// in the order they were added, so a guard of the router also runs before the responses the
+    /// router gives when no route matches.  
+    pub fn guard<P: Props + 'static>(&mut self) {
+        self.middleware(Guard::<P>::new());
+    }
+
+    /// Route the requests sent to a matching host to `router` instead of to this router.  
+    ///
+    /// The pattern is a host name whose labels are either matched literally or, when they are in
+    /// braces, captured into the params of the request.  Hosts are compared case-insensitively
+    /// and without their port, and are read from the `Host` header.  Patterns are tried in the
+    /// order they were added, requests for any other host are routed by this router.  
+    ///
+    /// A request routed to a host router is handled entirely by it, with its own middleware,
+    /// fallback and path policy.  The routes of host routers are listed by
+    /// [`routes`](#method.routes) and their names can be used with [`url_for`](#method.url_for).  
+    ///
+    /// ## Examples
+    /// ```
+    /// # #![feature(proc_macro_hygiene)]
+    /// # use windmill::*;
+    /// # use http_types::{Method, Response, StatusCode};
+    /// # #[endpoint] async fn dashboard() -> Result<Response, Error> { Ok(Response::new(StatusCode::Ok)) }
+    /// # #[endpoint] async fn status() -> Result<Response, Error> { Ok(Response::new(StatusCode::Ok)) }
+    /// let mut tenants = Router::new();
+    /// tenants.add(Method::Get, route!(/"dashboard"), dashboard);
+    ///
+    /// let mut api = Router::new();
+    /// api.add(Method::Get, route!(/"status"), status);
+    ///
+    /// let mut router = Router::new();
+    /// router.host("api.example.com", api);
+    /// router.host("{tenant}.example.com", tenants);
+    /// ```
+    /// Above, a request for `http://acme.example.com/dashboard` is handled by `dashboard` with
+    /// `tenant` set to `acme` in its params, while `api.example.com` is handled by the `api`
+    /// router.  
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the pattern has an empty label or unbalanced braces, if `router` has host
+    /// routers of its own, or if a route of `router` has the same name as a route that was already
+    /// added.  
+    pub fn host(&mut self, pattern: &'static str, router: Router) {
+        assert!(
+            router.hosts.is_empty(),
+            "a host router cannot have host routers of its own"
+        );
+
+        let names = router
+            .table
+            .values()
+            .flat_map(|tree| tree.routes.iter())
+            .filter_map(|route| route.name);
+
+        for name in names {
+            if self.named(name).is_some() {
+                panic!("{}", RouteError::DuplicateName(name));
+            }
+        }
+
+        self.hosts
+            .push((HostPattern::new(pattern), Arc::new(router)));
+    }
+
+    fn insert(&mut self, method: Method, route: Route) -> Result<(), RouteError> {
+        if let Some(name) = route.name {
+            if self.named(name).is_some() {
+                return Err(RouteError::DuplicateName(name));
+            }
+        }
+
+        self.table
+            .entry(method)
+            .or_insert_with(Tree::new)
+            .insert(route)
+            .map_err(|(route, existing)| RouteError::Duplicate {
+                method,
+                route: route.to_string(),
+                existing: existing.to_string(),
             })
+    }
+
+    pub(crate) async fn lookup(
+        self: Arc<Self>,
+        req: http_types::Request,
+    ) -> Box<dyn Future<Output = http_types::Response> + Unpin + Send + Sync> {
+        let host = match self.hosts.is_empty() {
+            true => None,
+            false => request_host(&req).and_then(|host| {
+                self.hosts.iter().find_map(|(pattern, router)| {
+                    pattern
+                        .matches(&host)
+                        .map(|params| (router.clone(), params))
+                })
+            }),
         };
 
-        route.handler = Some(Box::new(handler));
-        entry.push(route);
+        match host {
+            Some((router, params)) => router.dispatch(req, params).await,
+            None => self.dispatch(req, Params::new()).await,
+        }
     }
 
-    pub(crate) async fn lookup(
+    /// Route a request by its path and run whatever answers it wrapped in the middleware of the
+    /// router.
+    async fn dispatch(
         self: Arc<Self>,
         req: http_types::Request,
+        host_params: Params,
     ) -> Box<dyn Future<Output = http_types::Response> + Unpin + Send + Sync> {
+        let (endpoint, params) = self.route(&req, host_params);
+        let next = Next::new(self.middleware.clone(), endpoint);
+        Box::new(next.run(req, params))
+    }
+
+    /// Pick what answers a request, with the params to run it with.  The params of the matching
+    /// route include the `host_params` captured from the host of the request.
+    fn route(&self, req: &http_types::Request, host_params: Params) -> (RouteFn, Params) {
         let method = req.method();
-        let raw_route = RawRoute::from_path(req.url().path().into());
+        let normalized = normalize_path(req.url().path());
 
-        match self
-            .table
-            .get(&method)
-            .map(|routes| routes.iter().find(|route| paths_match(route, &raw_route)))
-        {
-            Some(Some(route)) => {
-                let mut params = HashMap::new();
-
-                route.dynamic_segments.iter().for_each(|dynamic_segment| {
-                    params.insert(
-                        dynamic_segment.name,
-                        raw_route.raw_segments[dynamic_segment.position]
-                            .value
-                            .into(),
-                    );
+        let path = match self.path_policy {
+            PathPolicy::Strict => req.url().path(),
+            PathPolicy::Redirect => {
+                let path = req.url().path();
+                let canonical = RawRoute::from_path(&normalized);
+                let matches =
+                    canonical.is_ok_and(|canonical| !self.allowed_methods(&canonical).is_empty());
+
+                if path != normalized && matches {
+                    let location = match req.url().query() {
+                        Some(query) => format!("{}?{}", normalized, query),
+                        None => normalized.clone(),
+                    };
+
+                    return (respond(move || redirect(method, &location)), host_params);
+                }
+
+                path
+            }
+            PathPolicy::Lenient => &normalized,
+        };
+
+        let raw_route = match RawRoute::from_path(path) {
+            Ok(raw_route) => raw_route,
+            Err(e) => {
+                let Error { code, msg } = e;
+                let respond = respond(move || {
+                    Error {
+                        code,
+                        msg: msg.clone(),
+                    }
+                    .into_response()
                 });
 
-                Box::new((route.handler.as_ref().unwrap())(req, params))
+                return (respond, host_params);
             }
-            _ => Box::new(Box::pin(not_found())),
+        };
+
+        let params = |route: &Route| {
+            let mut params = host_params.clone();
+            params.extend(route.params(&raw_route));
+            params
+        };
+
+        match self.find(method, &raw_route, req) {
+            Some(Ok(route)) => (endpoint(route), params(route)),
+            Some(Err(code)) => (status(code), host_params),
+            None => match (method, self.find(Method::Get, &raw_route, req)) {
+                (Method::Head, Some(Ok(route))) => {
+                    let endpoint = endpoint(route);
+                    let without_body: RouteFn =
+                        Arc::new(move |req, params| Box::pin(without_body(endpoint(req, params))));
+
+                    (without_body, params(route))
+                }
+                (Method::Head, Some(Err(code))) => (status(code), host_params),
+                _ => {
+                    let allowed = self.allowed_methods(&raw_route);
+
+                    if allowed.is_empty() {
+                        match self.fallbacks.find_prefix(&raw_route) {
+                            Some(route) => (endpoint(route), params(route)),
+                            None => (status(StatusCode::NotFound), host_params),
+                        }
+                    } else if method == Method::Options {
+                        let options = respond(move || {
+                            allow(http_types::Response::new(StatusCode::NoContent), &allowed)
+                        });
+
+                        (options, host_params)
+                    } else {
+                        let method_not_allowed = respond(move || {
+                            allow(
+                                http_types::Response::new(StatusCode::MethodNotAllowed),
+                                &allowed,
+                            )
+                        });
+
+                        (method_not_allowed, host_params)
+                    }
+                }
+            },
         }
     }
-}
 
-fn paths_match(route: &Route, raw_route: &RawRoute) -> bool {
-    if raw_route.raw_segments.len() == route.static_segments.len() + route.dynamic_segments.len() {
-        let static_matches = || {
-            route
-                .static_segments
-                .iter()
-                .fold(true, |is_match, static_segment| {
-                    is_match && (&raw_route.raw_segments[static_segment.position] == static_segment)
-                })
-        };
+    /// Find the route for the method matching the raw route, negotiating between routes that
+    /// differ by media type.  Fails with the status to answer when no route is acceptable.
+    fn find(
+        &self,
+        method: Method,
+        raw_route: &RawRoute,
+        req: &http_types::Request,
+    ) -> Option<Result<&Route, StatusCode>> {
+        let routes = self.table.get(&method)?.find(raw_route);
 
-        let dynamic_matches = || {
-            route
-                .dynamic_segments
-                .iter()
-                .fold(true, |is_match, dynamic_segment| {
-                    is_match
-                        && (&raw_route.raw_segments[dynamic_segment.position] == dynamic_segment)
-                })
-        };
+        match routes.is_empty() {
+            true => None,
+            false => Some(negotiate(&routes, req)),
+        }
+    }
+
+    /// The methods that have a route matching the raw route, sorted by name.  `HEAD` and `OPTIONS`
+    /// are included whenever the router would answer them automatically.
+    fn allowed_methods(&self, raw_route: &RawRoute) -> Vec<Method> {
+        let mut allowed: Vec<Method> = self
+            .table
+            .iter()
+            .filter(|(_, tree)| !tree.find(raw_route).is_empty())
+            .map(|(method, _)| *method)
+            .collect();
+
+        if allowed.contains(&Method::Get) && !allowed.contains(&Method::Head) {
+            allowed.push(Method::Head);
+        }
 
-        static_matches() && dynamic_matches()
-    } else {
-        false
+        if !allowed.is_empty() && !allowed.contains(&Method::Options) {
+            allowed.push(Method::Options);
+        }
+
+        allowed.sort_by_key(|method| method.to_string());
+        allowed
     }
 }
 
-async fn not_found() -> http_types::Response {
-    http_types::Response::new(StatusCode::NotFound)
+/// The endpoint of the route wrapped in the middleware of the route.
+fn endpoint(route: &Route) -> RouteFn {
+    let middleware = route.middleware.clone();
+    let handler = route.handler.clone().unwrap();
+
+    Arc::new(move |req, params| Next::new(middleware.clone(), handler.clone()).run(req, params))
+}
+
+/// A route handler answering every request with the response `response` builds.
+fn respond(response: impl Fn() -> http_types::Response + Send + Sync + 'static) -> RouteFn {
+    Arc::new(move |_, _| {
+        let res = response();
+        Box::pin(async move { res })
+    })
+}
+
+/// A route handler answering every request with an empty response with the status code.
+fn status(code: StatusCode) -> RouteFn {
+    respond(move || http_types::Response::new(code))
 }
 
-fn response(code: StatusCode, mime: Mime) -> http_types::Response {
+fn redirect(method: Method, location: &str) -> http_types::Response {
+    let code = match method {
+        Method::Get | Method::Head => StatusCode::MovedPermanently,
+        _ => StatusCode::PermanentRedirect,
+    };
+
     let mut res = http_types::Response::new(code);
-    let _ = res.set_content_type(mime);
+    let _ = res.insert_header(HeaderName::from_str("location").unwrap(), location);
+    res
+}
+
+/// Drop the body of a response while keeping the length it advertises, used to answer `HEAD`
+/// requests with the matching `GET` route.
+async fn without_body(res: ResponseFuture) -> http_types::Response {
+    let mut res = res.await;
+    let body = res.take_body();
+    res.set_body(Body::from_reader(io::empty(), body.len()));
+    res
+}
+
+fn allow(mut res: http_types::Response, allowed: &[Method]) -> http_types::Response {
+    let allow = allowed
+        .iter()
+        .map(|method| method.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let _ = res.insert_header(HeaderName::from_str("allow").unwrap(), allow);
     res
 }
+
+/// Wrap an endpoint into a route handler that turns errors into responses.
+fn handler(endpoint: impl Endpoint + Send + Sync) -> RouteFn {
+    Arc::new(
+        move |req: http_types::Request, params: Params| -> ResponseFuture {
+            Box::pin(async move { endpoint.call(req, params).await.into_response() })
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use http_types::{headers::HeaderName, mime, Method, Request, Response, StatusCode, Url};
+    use std::{
+        str::FromStr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    #[endpoint]
+    async fn first() -> &'static str {
+        "first"
+    }
+
+    #[endpoint]
+    async fn second() -> &'static str {
+        "second"
+    }
+
+    /// The params of a request, sorted by name.
+    struct Captured(Vec<(&'static str, String)>);
+
+    impl Props for Captured {
+        type Fut = PropsFuture<Self>;
+
+        fn call(req: Request, params: Params) -> Self::Fut {
+            let mut captured: Vec<_> = params.clone().into_iter().collect();
+            captured.sort();
+            Box::pin(async move { Ok((req, params, Captured(captured))) })
+        }
+    }
+
+    #[endpoint]
+    async fn echo(captured: Captured) -> String {
+        format!("{:?}", captured.0)
+    }
+
+    fn send(router: &Arc<Router>, method: Method, path: &str) -> Response {
+        send_with(router, method, path, &[])
+    }
+
+    fn send_with(
+        router: &Arc<Router>,
+        method: Method,
+        path: &str,
+        headers: &[(&str, &str)],
+    ) -> Response {
+        let url = Url::parse(&format!("http://localhost{}", path)).unwrap();
+        let mut req = Request::new(method, url);
+
+        for (name, value) in headers {
+            let _ = req.insert_header(HeaderName::from_str(name).unwrap(), *value);
+        }
+
+        async_std::task::block_on(async { router.clone().lookup(req).await.await })
+    }
+
+    fn body(mut res: Response) -> String {
+        async_std::task::block_on(res.body_string()).unwrap()
+    }
+
+    fn header(res: &Response, name: &str) -> Option<String> {
+        res.header(&HeaderName::from_str(name).unwrap())
+            .and_then(|values| values.first())
+            .map(|value| value.as_str().to_string())
+    }
+
+    #[test]
+    fn static_segments_take_precedence() {
+        let mut router = Router::new();
+        router.add(Method::Get, route!(/a/b/c), first);
+        router.add(Method::Get, route!(/"a"/b/c), second);
+        let router = Arc::new(router);
+
+        assert_eq!(body(send(&router, Method::Get, "/a/b/c")), "second");
+        assert_eq!(body(send(&router, Method::Get, "/z/b/c")), "first");
+        assert_eq!(
+            send(&router, Method::Get, "/a/b").status(),
+            StatusCode::NotFound
+        );
+    }
+
+    #[test]
+    fn backtracks_to_dynamic_segments() {
+        let mut router = Router::new();
+        router.add(Method::Get, route!(/"a"/"b"/"c"), first);
+        router.add(Method::Get, route!(/x/"b"/"d"), second);
+        let router = Arc::new(router);
+
+        assert_eq!(body(send(&router, Method::Get, "/a/b/c")), "first");
+        assert_eq!(body(send(&router, Method::Get, "/a/b/d")), "second");
+        assert_eq!(
+            send(&router, Method::Get, "/a/b/e").status(),
+            StatusCode::NotFound
+        );
+    }
+
+    #[test]
+    fn method_not_allowed() {
+        let mut router = Router::new();
+        router.add(Method::Get, route!(/"users"/id), first);
+        router.add(Method::Post, route!(/"users"/id), second);
+        router.add(Method::Delete, route!(/"posts"), second);
+        let router = Arc::new(router);
+
+        let res = send(&router, Method::Put, "/users/1");
+        assert_eq!(res.status(), StatusCode::MethodNotAllowed);
+        assert_eq!(header(&res, "allow").unwrap(), "GET, HEAD, OPTIONS, POST");
+
+        let res = send(&router, Method::Get, "/posts");
+        assert_eq!(res.status(), StatusCode::MethodNotAllowed);
+        assert_eq!(header(&res, "allow").unwrap(), "DELETE, OPTIONS");
+
+        let res = send(&router, Method::Put, "/comments");
+        assert_eq!(res.status(), StatusCode::NotFound);
+        assert_eq!(header(&res, "allow"), None);
+    }
+
+    #[endpoint]
+    async fn explicit() -> &'static str {
+        "explicit"
+    }
+
+    #[test]
+    fn head_runs_get_without_body() {
+        let mut router = Router::new();
+        router.add(Method::Get, route!(/"users"), first);
+        router.add(Method::Post, route!(/"posts"), first);
+        let router = Arc::new(router);
+
+        let res = send(&router, Method::Head, "/users");
+        assert_eq!(res.status(), StatusCode::Ok);
+        assert_eq!(res.len(), Some("first".len()));
+        assert_eq!(body(res), "");
+
+        let res = send(&router, Method::Head, "/posts");
+        assert_eq!(res.status(), StatusCode::MethodNotAllowed);
+    }
+
+    #[test]
+    fn options_lists_allowed_methods() {
+        let mut router = Router::new();
+        router.add(Method::Get, route!(/"users"), first);
+        router.add(Method::Post, route!(/"users"), second);
+        let router = Arc::new(router);
+
+        let res = send(&router, Method::Options, "/users");
+        assert_eq!(res.status(), StatusCode::NoContent);
+        assert_eq!(header(&res, "allow").unwrap(), "GET, HEAD, OPTIONS, POST");
+
+        let res = send(&router, Method::Options, "/posts");
+        assert_eq!(res.status(), StatusCode::NotFound);
+    }
+
+    #[test]
+    fn explicit_head_and_options_routes_take_priority() {
+        let mut router = Router::new();
+        router.add(Method::Get, route!(/"users"), first);
+        router.add(Method::Head, route!(/"users"), explicit);
+        router.add(Method::Options, route!(/"users"), explicit);
+        let router = Arc::new(router);
+
+        let res = send(&router, Method::Head, "/users");
+        assert_eq!(res.status(), StatusCode::Ok);
+        assert_eq!(body(res), "explicit");
+
+        let res = send(&router, Method::Options, "/users");
+        assert_eq!(res.status(), StatusCode::Ok);
+        assert_eq!(body(res), "explicit");
+    }
+
+    #[test]
+    fn catch_all_captures_one_or_more_segments() {
+        let mut router = Router::new();
+        router.add(Method::Get, route!(/"static"/path..), echo);
+        let router = Arc::new(router);
+
+        assert_eq!(
+            body(send(&router, Method::Get, "/static/css")),
+            r#"[("path", "css")]"#
+        );
+        assert_eq!(
+            body(send(&router, Method::Get, "/static/css/site.css")),
+            r#"[("path", "css/site.css")]"#
+        );
+        assert_eq!(
+            send(&router, Method::Get, "/static").status(),
+            StatusCode::NotFound
+        );
+        assert_eq!(
+            send(&router, Method::Get, "/static/").status(),
+            StatusCode::NotFound
+        );
+    }
+
+    #[test]
+    fn pattern_segments_reject_other_segments() {
+        let mut router = Router::new();
+        router.add(Method::Get, route!(/"files"/name ~ "[a-z]+\\.png"), echo);
+        router.add(Method::Get, route!(/"images"/name ~ "[a-z]+\\.png"), echo);
+        router.add(Method::Get, route!(/"images"/slug), first);
+        let router = Arc::new(router);
+
+        assert_eq!(
+            body(send(&router, Method::Get, "/files/cat.png")),
+            r#"[("name", "cat.png")]"#
+        );
+        assert_eq!(
+            send(&router, Method::Get, "/files/cat.gif").status(),
+            StatusCode::NotFound
+        );
+        assert_eq!(
+            send(&router, Method::Get, "/files/xcat.pngx").status(),
+            StatusCode::NotFound
+        );
+
+        assert_eq!(
+            body(send(&router, Method::Get, "/images/cat.png")),
+            r#"[("name", "cat.png")]"#
+        );
+        assert_eq!(body(send(&router, Method::Get, "/images/cat.gif")), "first");
+    }
+
+    #[test]
+    fn mounted_routes_capture_prefix_params() {
+        let mut users = Router::new();
+        users.add(Method::Get, route!(/"users"/id), echo);
+        users.add(Method::Get, route!(/), first);
+
+        let mut router = Router::new();
+        router.mount(route!(/"tenants"/tenant_id), users);
+        let router = Arc::new(router);
+
+        assert_eq!(
+            body(send(&router, Method::Get, "/tenants/acme/users/1")),
+            r#"[("id", "1"), ("tenant_id", "acme")]"#
+        );
+        assert_eq!(body(send(&router, Method::Get, "/tenants/acme")), "first");
+        assert_eq!(
+            send(&router, Method::Get, "/users/1").status(),
+            StatusCode::NotFound
+        );
+    }
+
+    fn powered_by(req: Request, params: Params, next: Next) -> ResponseFuture {
+        Box::pin(async move {
+            let mut res = next.run(req, params).await;
+            let _ = res.insert_header(HeaderName::from_str("x-powered-by").unwrap(), "windmill");
+            res
+        })
+    }
+
+    #[test]
+    fn middleware_wraps_every_response() {
+        let mut api = Router::new();
+        api.fallback(second);
+
+        let mut router = Router::new();
+        router.middleware(powered_by);
+        router.path_policy(PathPolicy::Redirect);
+        router.add(Method::Get, route!(/"users"/id), echo);
+        router.add(Method::Get, route!(/"reports").produces(mime::JSON), first);
+        router.mount(route!(/"api"), api);
+        let router = Arc::new(router);
+
+        let requests = [
+            (Method::Get, "/users/1", StatusCode::Ok),
+            (Method::Head, "/users/1", StatusCode::Ok),
+            (Method::Options, "/users/1", StatusCode::NoContent),
+            (Method::Get, "/users/1/", StatusCode::MovedPermanently),
+            (Method::Get, "/users/%FF", StatusCode::BadRequest),
+            (Method::Get, "/posts", StatusCode::NotFound),
+            (Method::Post, "/users/1", StatusCode::MethodNotAllowed),
+            (Method::Get, "/api/unknown", StatusCode::Ok),
+        ];
+
+        for (method, path, code) in requests.iter() {
+            let res = send(&router, *method, path);
+            assert_eq!(res.status(), *code, "{} {}", method, path);
+            assert_eq!(header(&res, "x-powered-by").unwrap(), "windmill");
+        }
+
+        let res = send_with(&router, Method::Get, "/reports", &[("accept", "text/html")]);
+        assert_eq!(res.status(), StatusCode::NotAcceptable);
+        assert_eq!(header(&res, "x-powered-by").unwrap(), "windmill");
+
+        assert_eq!(
+            body(send(&router, Method::Get, "/users/1")),
+            r#"[("id", "1")]"#
+        );
+    }
+
+    #[test]
+    fn prefix_middleware_wraps_mounted_routes() {
+        let mut admin = Router::new();
+        admin.add(Method::Get, route!(/"users"), first);
+
+        let mut router = Router::new();
+        router.mount(route!(/"admin").middleware(powered_by), admin);
+        router.add(Method::Get, route!(/"users"), second);
+        let router = Arc::new(router);
+
+        let res = send(&router, Method::Get, "/admin/users");
+        assert_eq!(header(&res, "x-powered-by").unwrap(), "windmill");
+        assert_eq!(body(res), "first");
+
+        let res = send(&router, Method::Get, "/users");
+        assert_eq!(header(&res, "x-powered-by"), None);
+        assert_eq!(body(res), "second");
+    }
+
+    static COUNTED: AtomicUsize = AtomicUsize::new(0);
+
+    #[endpoint]
+    async fn counted() -> &'static str {
+        COUNTED.fetch_add(1, Ordering::SeqCst);
+        "counted"
+    }
+
+    struct Deny;
+
+    impl Props for Deny {
+        type Fut = PropsFuture<Self>;
+
+        fn call(_: Request, _: Params) -> Self::Fut {
+            Box::pin(async move {
+                Err(Error {
+                    code: StatusCode::Forbidden,
+                    msg: "denied".into(),
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn group_guards_short_circuit_endpoints() {
+        let mut router = Router::new();
+        router.group(route!(/"admin"), |admin| {
+            admin.guard::<Deny>();
+            admin.add(Method::Get, route!(/"users"), counted);
+            admin.fallback(counted);
+        });
+        router.group(route!(/"api").middleware(powered_by), |api| {
+            api.add(Method::Get, route!(/"users"), first);
+        });
+        router.add(Method::Get, route!(/"users"), second);
+        let router = Arc::new(router);
+
+        for path in ["/admin/users", "/admin/unknown"].iter() {
+            let res = send(&router, Method::Get, path);
+            assert_eq!(res.status(), StatusCode::Forbidden, "{}", path);
+        }
+        assert_eq!(COUNTED.load(Ordering::SeqCst), 0);
+
+        let res = send(&router, Method::Get, "/api/users");
+        assert_eq!(header(&res, "x-powered-by").unwrap(), "windmill");
+        assert_eq!(body(res), "first");
+
+        assert_eq!(body(send(&router, Method::Get, "/users")), "second");
+    }
+
+    #[test]
+    fn constrained_routes_conflict_by_constraint() {
+        let mut router = Router::new();
+        router.add(Method::Get, route!(/"files"/name ~ "[a-z]+"), first);
+        router.add(Method::Get, route!(/"users"/id: u64), first);
+
+        assert!(router
+            .try_add(Method::Get, route!(/"files"/file ~ "[a-z]+"), second)
+            .is_err());
+        assert!(router
+            .try_add(Method::Get, route!(/"users"/user_id: u64), second)
+            .is_err());
+        assert!(router
+            .try_add(Method::Get, route!(/"files"/name ~ "[0-9]+"), second)
+            .is_ok());
+        assert!(router
+            .try_add(Method::Get, route!(/"users"/id: i64), second)
+            .is_ok());
+        let router = Arc::new(router);
+
+        assert_eq!(body(send(&router, Method::Get, "/files/abc")), "first");
+        assert_eq!(body(send(&router, Method::Get, "/files/123")), "second");
+        assert_eq!(body(send(&router, Method::Get, "/users/1")), "first");
+        assert_eq!(body(send(&router, Method::Get, "/users/-1")), "second");
+    }
+
+    fn path_policy_router(policy: PathPolicy) -> Arc<Router> {
+        let mut router = Router::new();
+        router.path_policy(policy);
+        router.add(Method::Get, route!(/"hello"/name), echo);
+        router.add(Method::Post, route!(/"hello"/name), echo);
+        router.add(Method::Get, route!(/), first);
+        Arc::new(router)
+    }
+
+    #[test]
+    fn strict_paths_match_exactly() {
+        let router = path_policy_router(PathPolicy::Strict);
+
+        assert_eq!(
+            body(send(&router, Method::Get, "/hello/bob")),
+            r#"[("name", "bob")]"#
+        );
+        assert_eq!(body(send(&router, Method::Get, "/")), "first");
+        assert_eq!(
+            send(&router, Method::Get, "/hello/bob/").status(),
+            StatusCode::NotFound
+        );
+        assert_eq!(
+            send(&router, Method::Get, "//hello/bob").status(),
+            StatusCode::NotFound
+        );
+    }
+
+    #[test]
+    fn redirect_paths_to_their_canonical_form() {
+        let router = path_policy_router(PathPolicy::Redirect);
+
+        let res = send(&router, Method::Get, "/hello/bob/");
+        assert_eq!(res.status(), StatusCode::MovedPermanently);
+        assert_eq!(header(&res, "location").unwrap(), "/hello/bob");
+
+        let res = send(&router, Method::Head, "//hello//bob");
+        assert_eq!(res.status(), StatusCode::MovedPermanently);
+        assert_eq!(header(&res, "location").unwrap(), "/hello/bob");
+
+        let res = send(&router, Method::Post, "/hello/bob/?page=2&sort=asc");
+        assert_eq!(res.status(), StatusCode::PermanentRedirect);
+        assert_eq!(
+            header(&res, "location").unwrap(),
+            "/hello/bob?page=2&sort=asc"
+        );
+
+        let res = send(&router, Method::Get, "/goodbye/bob/");
+        assert_eq!(res.status(), StatusCode::NotFound);
+        assert_eq!(header(&res, "location"), None);
+
+        assert_eq!(
+            body(send(&router, Method::Get, "/hello/bob")),
+            r#"[("name", "bob")]"#
+        );
+    }
+
+    #[test]
+    fn lenient_paths_match_their_canonical_form() {
+        let router = path_policy_router(PathPolicy::Lenient);
+
+        for path in ["/hello/bob/", "//hello//bob", "/hello/bob//"].iter() {
+            let res = send(&router, Method::Get, path);
+            assert_eq!(res.status(), StatusCode::Ok, "{}", path);
+            assert_eq!(body(res), r#"[("name", "bob")]"#);
+        }
+
+        assert_eq!(body(send(&router, Method::Get, "//")), "first");
+    }
+
+    #[test]
+    fn path_segments_are_percent_decoded() {
+        let mut router = Router::new();
+        router.add(Method::Get, route!(/"hello"/name), echo);
+        router.add(Method::Get, route!(/"a b"/"c"), first);
+        let router = Arc::new(router);
+
+        assert_eq!(
+            body(send(&router, Method::Get, "/hello/John%20Doe")),
+            r#"[("name", "John Doe")]"#
+        );
+        assert_eq!(
+            body(send(&router, Method::Get, "/hello/a%2Fb")),
+            r#"[("name", "a/b")]"#
+        );
+        assert_eq!(body(send(&router, Method::Get, "/a%20b/c")), "first");
+        assert_eq!(body(send(&router, Method::Get, "/a%20b/%63")), "first");
+
+        for path in ["/hello/%FF", "/hello/%4", "/hello/%zz"].iter() {
+            let res = send(&router, Method::Get, path);
+            assert_eq!(res.status(), StatusCode::BadRequest, "{}", path);
+        }
+    }
+
+    #[test]
+    fn optional_segments_match_with_and_without_them() {
+        let mut router = Router::new();
+        router.add(Method::Get, route!(/"posts"/id?), echo);
+        router.add(Method::Get, route!(/"list"/["page"/page]), echo);
+        let router = Arc::new(router);
+
+        assert_eq!(body(send(&router, Method::Get, "/posts")), "[]");
+        assert_eq!(
+            body(send(&router, Method::Get, "/posts/3")),
+            r#"[("id", "3")]"#
+        );
+        assert_eq!(body(send(&router, Method::Get, "/list")), "[]");
+        assert_eq!(
+            body(send(&router, Method::Get, "/list/page/2")),
+            r#"[("page", "2")]"#
+        );
+        assert_eq!(
+            send(&router, Method::Get, "/list/page").status(),
+            StatusCode::NotFound
+        );
+        assert_eq!(
+            send(&router, Method::Get, "/posts/3/4").status(),
+            StatusCode::NotFound
+        );
+    }
+
+    #[test]
+    fn route_table_includes_host_routers() {
+        let mut tenants = Router::new();
+        tenants.add_named("dashboard", Method::Get, route!(/"dashboard"/id), first);
+
+        let mut router = Router::new();
+        router.add_named("home", Method::Get, route!(/), first);
+        router.host("{tenant}.example.com", tenants);
+
+        let routes: Vec<_> = router
+            .routes()
+            .map(|route| (route.host(), route.pattern(), route.name()))
+            .collect();
+        assert_eq!(
+            routes,
+            [
+                (None, "/".to_string(), Some("home")),
+                (
+                    Some("{tenant}.example.com"),
+                    "/dashboard/{id}".to_string(),
+                    Some("dashboard")
+                ),
+            ]
+        );
+
+        assert_eq!(
+            router.to_string(),
+            "GET / (home)\nGET {tenant}.example.com/dashboard/{id} (dashboard)\n"
+        );
+        assert_eq!(
+            router.url_for("dashboard", &[("id", "7")]).unwrap(),
+            "/dashboard/7"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "a route named `dashboard` already exists")]
+    fn host_routers_cannot_reuse_route_names() {
+        let mut tenants = Router::new();
+        tenants.add_named("dashboard", Method::Get, route!(/"dashboard"), first);
+
+        let mut router = Router::new();
+        router.host("{tenant}.example.com", tenants);
+        router.add_named("dashboard", Method::Get, route!(/"dashboard"), first);
+    }
+
+    #[test]
+    fn host_captures_are_merged_into_params() {
+        let mut tenants = Router::new();
+        tenants.add(Method::Get, route!(/"users"/id), echo);
+
+        let mut router = Router::new();
+        router.host("{tenant}.example.com", tenants);
+        router.add(Method::Get, route!(/"users"/id), first);
+        let router = Arc::new(router);
+
+        let res = send_with(
+            &router,
+            Method::Get,
+            "/users/1",
+            &[("host", "ACME.example.com:8080")],
+        );
+        assert_eq!(body(res), r#"[("id", "1"), ("tenant", "acme")]"#);
+
+        let res = send_with(&router, Method::Get, "/users/1", &[("host", "example.com")]);
+        assert_eq!(body(res), "first");
+    }
+
+    #[test]
+    fn routes_are_negotiated_by_media_type() {
+        let mut router = Router::new();
+        router.add(
+            Method::Get,
+            route!(/"reports"/id).produces(mime::JSON),
+            first,
+        );
+        router.add(
+            Method::Get,
+            route!(/"reports"/id).produces(mime::HTML),
+            second,
+        );
+        router.add(Method::Get, route!(/"users").produces(mime::JSON), first);
+        router.add(Method::Get, route!(/"users"), second);
+        router.add(Method::Post, route!(/"users").consumes(mime::JSON), first);
+        let router = Arc::new(router);
+
+        let get = |path, accept| {
+            let res = send_with(&router, Method::Get, path, &[("accept", accept)]);
+            match res.status() {
+                StatusCode::Ok => body(res),
+                code => (code as u16).to_string(),
+            }
+        };
+
+        assert_eq!(body(send(&router, Method::Get, "/reports/1")), "first");
+        assert_eq!(get("/reports/1", "text/html"), "second");
+        assert_eq!(
+            get("/reports/1", "application/json;q=0.5, text/*;q=0.9"),
+            "second"
+        );
+        assert_eq!(get("/reports/1", "*/*;q=0.1, text/html;q=0"), "first");
+        assert_eq!(get("/reports/1", "text/csv"), "406");
+
+        assert_eq!(get("/users", "application/json"), "first");
+        assert_eq!(get("/users", "text/csv"), "second");
+
+        let post = |content_type| {
+            let headers = [("content-type", content_type)];
+            send_with(&router, Method::Post, "/users", &headers).status()
+        };
+
+        assert_eq!(post("application/json; charset=utf-8"), StatusCode::Ok);
+        assert_eq!(post("text/csv"), StatusCode::UnsupportedMediaType);
+    }
+
+    #[test]
+    #[should_panic(expected = "a mount prefix cannot have a name or media types")]
+    fn mount_prefixes_cannot_have_media_types() {
+        let mut router = Router::new();
+        router.mount(route!(/"api").produces(mime::JSON), Router::new());
+    }
+
+    #[endpoint]
+    async fn created() -> (StatusCode, Json<Vec<&'static str>>) {
+        (StatusCode::Created, Json(vec!["bob"]))
+    }
+
+    #[endpoint]
+    async fn missing() -> Option<&'static str> {
+        None
+    }
+
+    #[endpoint]
+    async fn bytes() -> Vec<u8> {
+        vec![1, 2, 3]
+    }
+
+    #[endpoint]
+    async fn conflict() -> Result<&'static str, (StatusCode, &'static str)> {
+        Err((StatusCode::Conflict, "taken"))
+    }
+
+    #[test]
+    fn return_values_are_converted_into_responses() {
+        let mut router = Router::new();
+        router.add(Method::Post, route!(/"users"), created);
+        router.add(Method::Get, route!(/"users"/id), missing);
+        router.add(Method::Get, route!(/"avatar"), bytes);
+        router.add(Method::Put, route!(/"users"), conflict);
+        let router = Arc::new(router);
+
+        let res = send(&router, Method::Post, "/users");
+        assert_eq!(res.status(), StatusCode::Created);
+        assert_eq!(header(&res, "content-type").unwrap(), "application/json");
+        assert_eq!(body(res), r#"["bob"]"#);
+
+        let res = send(&router, Method::Get, "/users/1");
+        assert_eq!(res.status(), StatusCode::NotFound);
+
+        let mut res = send(&router, Method::Get, "/avatar");
+        assert_eq!(res.status(), StatusCode::Ok);
+        assert_eq!(
+            header(&res, "content-type").unwrap(),
+            "application/octet-stream"
+        );
+        assert_eq!(
+            async_std::task::block_on(res.body_bytes()).unwrap(),
+            [1, 2, 3]
+        );
+
+        let res = send(&router, Method::Put, "/users");
+        assert_eq!(res.status(), StatusCode::Conflict);
+        assert_eq!(body(res), "taken");
+    }
+
+    trait Greeting {
+        const TEXT: &'static str;
+    }
+
+    struct English;
+
+    impl Greeting for English {
+        const TEXT: &'static str = "hello";
+    }
+
+    #[endpoint(GET, /"greeting")]
+    async fn greet<G>() -> &'static str
+    where
+        G: Greeting + 'static,
+    {
+        G::TEXT
+    }
+
+    #[test]
+    fn generic_endpoints_are_instantiated_when_added() {
+        let mut router = Router::new();
+        router.add(Method::Get, route!(/"hello"), greet::<English>);
+        router.register(routes![greet::<English>]);
+        let router = Arc::new(router);
+
+        assert_eq!(body(send(&router, Method::Get, "/hello")), "hello");
+        assert_eq!(body(send(&router, Method::Get, "/greeting")), "hello");
+    }
+}
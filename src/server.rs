@@ -1,11 +1,10 @@
-use crate::{config::Config, router::Router};
+use crate::{config::Config, handle::RouterHandle};
 use async_std::{
     net::{TcpListener, TcpStream},
     prelude::*,
     task,
 };
 use http_types::Error;
-use std::sync::Arc;
 
 /// The server that listens for requests.  
 pub struct Server {
@@ -25,7 +24,9 @@ impl Server {
         Self { config }
     }
 
-    /// Start accepting requests on the server using the provided router.  
+    /// Start accepting requests on the server using the provided router.  Pass a
+    /// [`RouterHandle`](struct.RouterHandle.html) instead to swap the router while the server is
+    /// running.  
     ///
     /// ## Examples
     /// ```no_run
@@ -38,8 +39,8 @@ impl Server {
     ///     println!("{}", e);
     /// }
     /// ```
-    pub fn run(self, router: Router) -> Result<(), Box<dyn std::error::Error>> {
-        let router = Arc::new(router);
+    pub fn run(self, router: impl Into<RouterHandle>) -> Result<(), Box<dyn std::error::Error>> {
+        let router = router.into();
         Ok(task::block_on(async {
             let listener = TcpListener::bind(self.config.addr())
                 .await
@@ -66,10 +67,9 @@ impl Server {
     }
 }
 
-async fn accept(addr: String, stream: TcpStream, router: Arc<Router>) -> Result<(), Error> {
-    let router = router.clone();
+async fn accept(addr: String, stream: TcpStream, router: RouterHandle) -> Result<(), Error> {
     async_h1::accept(&addr, stream.clone(), |req| async {
-        let response = router.clone().lookup(req).await.await;
+        let response = router.current().lookup(req).await.await;
         Ok(response)
     })
     .await
@@ -0,0 +1,246 @@
+use crate::{
+    negotiation::same_media_types,
+    route::{Constraint, RawRoute, RawSegment, Route, Segment},
+};
+use regex::Regex;
+use std::collections::HashMap;
+
+/// A segment tree holding the routes registered for a single method.
+///
+/// Every route is stored once in `routes` and the nodes of the tree refer to it by index.  While
+/// walking the tree the static children of a node are always tried before its dynamic children, so
+/// a static segment beats a dynamic segment no matter which route was added first.  Constrained
+/// dynamic children are tried before the unconstrained one, and a catch-all is only used once none
+/// of them lead to a match.
+///
+/// Several routes of the same shape can be stored at a node when they differ by the media types
+/// they consume or produce, the router picks one of them by content negotiation.
+pub(crate) struct Tree {
+    pub(crate) routes: Vec<Route>,
+    root: Node,
+}
+
+#[derive(Default)]
+struct Node {
+    statics: HashMap<&'static str, Node>,
+    dynamics: Vec<(Matcher, Node)>,
+    catch_all: Vec<usize>,
+    route: Vec<usize>,
+}
+
+impl Tree {
+    pub(crate) fn new() -> Self {
+        Self {
+            routes: vec![],
+            root: Node::default(),
+        }
+    }
+
+    /// Insert a route into the tree.  If a route with the same shape and media types already
+    /// exists the new route is handed back together with the existing one, and the tree is left
+    /// untouched.  A route with optional segments is stored at every depth it matches.
+    pub(crate) fn insert(&mut self, route: Route) -> Result<(), (Box<Route>, &Route)> {
+        let segments = route.segments();
+        let depths = route.depths();
+        let catch_all = route.catch_all.is_some();
+
+        let existing = depths.iter().find_map(|depth| {
+            self.root
+                .get(&segments[..*depth], catch_all)?
+                .iter()
+                .copied()
+                .find(|existing| same_media_types(&self.routes[*existing], &route))
+        });
+
+        if let Some(existing) = existing {
+            return Err((Box::new(route), &self.routes[existing]));
+        }
+
+        let index = self.routes.len();
+        let mut node = &mut self.root;
+
+        for (depth, segment) in segments.iter().enumerate() {
+            if depths.contains(&depth) {
+                node.slot(catch_all).push(index);
+            }
+
+            node = node.child(segment);
+        }
+
+        node.slot(catch_all).push(index);
+
+        self.routes.push(route);
+        Ok(())
+    }
+
+    /// Find the routes matching the raw route, in the order they were added.  The cost of a
+    /// lookup depends on the number of segments in the path rather than on the number of routes in
+    /// the tree.
+    pub(crate) fn find(&self, raw_route: &RawRoute) -> Vec<&Route> {
+        self.root
+            .find(&raw_route.raw_segments)
+            .map_or(vec![], |indices| {
+                indices.iter().map(|index| &self.routes[*index]).collect()
+            })
+    }
+
+    /// Find the route with the most segments that matches the start of the raw route.
+    pub(crate) fn find_prefix(&self, raw_route: &RawRoute) -> Option<&Route> {
+        self.root
+            .find_prefix(&raw_route.raw_segments)
+            .map(|indices| &self.routes[indices[0]])
+    }
+}
+
+impl Node {
+    /// The slot for the routes ending after the segments, if the nodes along the way exist.
+    fn get(&self, segments: &[Segment], catch_all: bool) -> Option<&Vec<usize>> {
+        let mut node = self;
+        for segment in segments {
+            node = match segment {
+                Segment::Static(static_segment) => node.statics.get(static_segment.value)?,
+                Segment::Dynamic(dynamic_segment) => {
+                    let constraint = &dynamic_segment.constraint;
+                    let (_, child) = node
+                        .dynamics
+                        .iter()
+                        .find(|(matcher, _)| matcher.is(constraint))?;
+                    child
+                }
+            };
+        }
+
+        match catch_all {
+            true => Some(&node.catch_all),
+            false => Some(&node.route),
+        }
+    }
+
+    /// The slot for the routes ending at this node.
+    fn slot(&mut self, catch_all: bool) -> &mut Vec<usize> {
+        match catch_all {
+            true => &mut self.catch_all,
+            false => &mut self.route,
+        }
+    }
+
+    /// The child for the segment, created if it does not exist yet.
+    fn child(&mut self, segment: &Segment) -> &mut Node {
+        match segment {
+            Segment::Static(static_segment) => {
+                self.statics.entry(static_segment.value).or_default()
+            }
+            Segment::Dynamic(dynamic_segment) => self.dynamic(&dynamic_segment.constraint),
+        }
+    }
+
+    /// The dynamic child for the constraint, created if it does not exist yet.  The matcher of a
+    /// new child is built here, so a pattern is only compiled when a route needs a new node.
+    fn dynamic(&mut self, constraint: &Option<Constraint>) -> &mut Node {
+        let index = match self
+            .dynamics
+            .iter()
+            .position(|(matcher, _)| matcher.is(constraint))
+        {
+            Some(index) => index,
+            None => {
+                let index = match constraint {
+                    None => self.dynamics.len(),
+                    Some(_) => self
+                        .dynamics
+                        .iter()
+                        .position(|(matcher, _)| matches!(matcher, Matcher::Any))
+                        .unwrap_or(self.dynamics.len()),
+                };
+
+                self.dynamics
+                    .insert(index, (Matcher::new(constraint), Node::default()));
+                index
+            }
+        };
+
+        &mut self.dynamics[index].1
+    }
+
+    fn find(&self, raw_segments: &[RawSegment]) -> Option<&[usize]> {
+        match raw_segments.split_first() {
+            None => non_empty(&self.route),
+            Some((raw_segment, rest)) => self
+                .statics
+                .get(raw_segment.value.as_ref())
+                .and_then(|node| node.find(rest))
+                .or_else(|| {
+                    self.dynamics
+                        .iter()
+                        .filter(|(matcher, _)| matcher.matches(&raw_segment.value))
+                        .find_map(|(_, node)| node.find(rest))
+                })
+                .or_else(|| match (raw_segment.value.as_ref(), rest) {
+                    // A trailing slash leaves nothing for a catch-all to capture.
+                    ("", []) => None,
+                    _ => non_empty(&self.catch_all),
+                }),
+        }
+    }
+
+    fn find_prefix(&self, raw_segments: &[RawSegment]) -> Option<&[usize]> {
+        let deeper = match raw_segments.split_first() {
+            None => None,
+            Some((raw_segment, rest)) => self
+                .statics
+                .get(raw_segment.value.as_ref())
+                .and_then(|node| node.find_prefix(rest))
+                .or_else(|| {
+                    self.dynamics
+                        .iter()
+                        .filter(|(matcher, _)| matcher.matches(&raw_segment.value))
+                        .find_map(|(_, node)| node.find_prefix(rest))
+                }),
+        };
+
+        deeper.or_else(|| non_empty(&self.route))
+    }
+}
+
+fn non_empty(indices: &[usize]) -> Option<&[usize]> {
+    Some(indices).filter(|indices| !indices.is_empty())
+}
+
+/// Decides whether a raw segment can be captured by a dynamic segment.
+enum Matcher {
+    Any,
+    Type(&'static str, fn(&str) -> bool),
+    Pattern(&'static str, Regex),
+}
+
+impl Matcher {
+    fn new(constraint: &Option<Constraint>) -> Self {
+        match constraint {
+            Some(Constraint::Type(name, check)) => Matcher::Type(name, *check),
+            Some(Constraint::Pattern(pattern)) => Matcher::Pattern(
+                pattern,
+                Regex::new(&format!("^(?:{})$", pattern))
+                    .unwrap_or_else(|e| panic!("invalid segment pattern {:?}: {}", pattern, e)),
+            ),
+            None => Matcher::Any,
+        }
+    }
+
+    /// Whether the matcher was built from the constraint.
+    fn is(&self, constraint: &Option<Constraint>) -> bool {
+        match (self, constraint) {
+            (Matcher::Any, None) => true,
+            (Matcher::Type(name, _), Some(Constraint::Type(other, _))) => name == other,
+            (Matcher::Pattern(pattern, _), Some(Constraint::Pattern(other))) => pattern == other,
+            _ => false,
+        }
+    }
+
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            Matcher::Any => true,
+            Matcher::Type(_, check) => check(value),
+            Matcher::Pattern(_, regex) => regex.is_match(value),
+        }
+    }
+}
@@ -13,3 +13,76 @@ pub async fn read_body(req: &mut http_types::Request) -> Vec<u8> {
     }
     body
 }
+
+/// Normalize a request path into its canonical form.  
+///
+/// Empty segments are removed, which drops trailing slashes and collapses repeated slashes, `.`
+/// segments are removed and `..` segments remove the segment before them.  A `..` segment at the
+/// root is ignored.  
+///
+/// ## Examples
+/// ```
+/// # use windmill::normalize_path;
+/// assert_eq!(normalize_path("/hello/bob"), "/hello/bob");
+/// assert_eq!(normalize_path("/hello/bob/"), "/hello/bob");
+/// assert_eq!(normalize_path("//hello"), "/hello");
+/// assert_eq!(normalize_path("/a//b///c"), "/a/b/c");
+/// assert_eq!(normalize_path("/a/../b"), "/b");
+/// assert_eq!(normalize_path("/a/./b/."), "/a/b");
+/// assert_eq!(normalize_path("/../a"), "/a");
+/// assert_eq!(normalize_path("/a/b/../../.."), "/");
+/// assert_eq!(normalize_path("/"), "/");
+/// assert_eq!(normalize_path(""), "/");
+/// ```
+pub fn normalize_path(path: &str) -> String {
+    let mut segments = vec![];
+
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    format!("/{}", segments.join("/"))
+}
+
+/// Percent-encode every byte of a path segment that is not an unreserved character.
+pub(crate) fn percent_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Decode the percent-encoded bytes of a path segment.  Returns `None` if an escape is malformed or
+/// if the decoded bytes are not valid UTF-8.
+pub(crate) fn percent_decode(segment: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut rest = segment.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail.get(..2)?;
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+
+            bytes.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}